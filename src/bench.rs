@@ -0,0 +1,149 @@
+use std::{
+    collections::BTreeMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    device::{SerialDevice, TactileDevice},
+    event::{encode_glyph, Capabilities, Ev, EVENT_LEN, FEATURE_ECHO},
+    glyphs::glyph_duration,
+};
+
+/// Events per benchmark frame, cycled through so that frame length varies
+const EVENT_COUNTS: [usize; 6] = [1, 2, 4, 8, 16, 32];
+/// How long to wait for an echo before counting it as lost
+const ECHO_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Timings of one kind, in milliseconds
+#[derive(Default)]
+pub struct Samples(Vec<f64>);
+
+impl Samples {
+    pub fn push(&mut self, ms: f64) {
+        self.0.push(ms);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Nearest-rank percentile, `p` from 0 to 100
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let mut sorted = self.0.clone();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.saturating_sub(1)).copied()
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let n = self.0.len() as f64;
+        let mean = self.0.iter().sum::<f64>() / n;
+        Some((self.0.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt())
+    }
+}
+
+/// What `bench_link` measured. Measurements the firmware can't support are None.
+pub struct LinkReport {
+    pub writes: BTreeMap<usize, Samples>, // Host write time, by frame length in bytes
+    pub echo: Option<Samples>,            // Round trip of a PKT_ECHO as long as the frame
+    pub echoes_lost: usize,
+    pub onset: Option<Samples>, // From writing a frame to its PKT_STARTED
+    pub duration_error: Option<Samples>, // Reported playback length minus the glyph's length
+}
+
+/// `events` pulses `step_ms` apart, walking over the motors
+fn bench_glyph(events: usize, step_ms: u16, motor_count: u8) -> Vec<Ev> {
+    let mut glyph: Vec<Ev> = (0..events)
+        .map(|i| {
+            Ev::motor(
+                step_ms.saturating_mul(i as u16),
+                (i % motor_count as usize) as u8,
+            )
+        })
+        .collect();
+    glyph.push(Ev::end(step_ms.saturating_mul(events as u16)));
+    glyph
+}
+
+/// Sends `frames` glyphs of varying length with events `step_ms` apart, waiting for each one to
+/// finish before sending the next, and times the link on the way
+pub fn bench_link(
+    dev: &mut SerialDevice,
+    caps: &Capabilities,
+    frames: usize,
+    step_ms: u16,
+) -> anyhow::Result<LinkReport> {
+    // One event of the glyph is its EndGlyph
+    let max_events = (caps.max_glyph_len as usize / EVENT_LEN).checked_sub(1);
+    let glyphs: Vec<Vec<Ev>> = EVENT_COUNTS
+        .iter()
+        .filter(|&&events| max_events.is_some_and(|max| events <= max))
+        .map(|&events| bench_glyph(events, step_ms, caps.motor_count))
+        .collect();
+    let Some(first) = glyphs.first() else {
+        return Err(anyhow!(
+            "The firmware takes glyphs of up to {} bytes, too short for any of the test glyphs",
+            caps.max_glyph_len
+        ));
+    };
+    let mut report = LinkReport {
+        writes: BTreeMap::new(),
+        echo: caps.has(FEATURE_ECHO).then(Samples::default),
+        echoes_lost: 0,
+        onset: Some(Samples::default()),
+        duration_error: Some(Samples::default()),
+    };
+    // The first frame may have to wake the board up, keep it out of the timings
+    dev.send_glyph(first)?;
+    dev.wait_playback(Duration::from_secs(2))?;
+    for glyph in glyphs.iter().cycle().take(frames) {
+        let frame = encode_glyph(glyph);
+        let duration = Duration::from_millis(glyph_duration(glyph) as u64);
+
+        let start = Instant::now();
+        dev.send_glyph(glyph)?;
+        let written = start.elapsed();
+        report
+            .writes
+            .entry(frame.len())
+            .or_default()
+            .push(written.as_secs_f64() * 1000.0);
+
+        let playback = dev.wait_playback(duration + Duration::from_secs(1))?;
+        match playback.and(dev.playback_times()) {
+            Some((started, finished)) => {
+                if let Some(onset) = report.onset.as_mut() {
+                    onset.push(started.saturating_duration_since(start).as_secs_f64() * 1000.0);
+                }
+                if let Some(error) = report.duration_error.as_mut() {
+                    let played = finished.saturating_duration_since(started);
+                    error.push((played.as_secs_f64() - duration.as_secs_f64()) * 1000.0);
+                }
+            }
+            None => {
+                // The firmware doesn't report playback, all that's left is to wait it out
+                report.onset = None;
+                report.duration_error = None;
+                thread::sleep(duration + Duration::from_millis(100));
+            }
+        }
+
+        if let Some(echo) = report.echo.as_mut() {
+            match dev.echo(&frame, ECHO_TIMEOUT)? {
+                Some(rtt) => echo.push(rtt.as_secs_f64() * 1000.0),
+                None => report.echoes_lost += 1,
+            }
+        }
+    }
+    Ok(report)
+}
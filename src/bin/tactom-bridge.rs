@@ -0,0 +1,151 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use clap::Parser;
+use serialport::{ClearBuffer, SerialPort, TTYPort};
+use tactom_experiments::{discover::find_device, profile::Profile, sim};
+
+/// Makes a tactom device reachable over TCP, so that the experiment can run on another machine
+/// with "tcp:<host>:<port>" as its TTY_DEV. Serves one client at a time.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// serial device the tactom device is on, "auto" to find it or "sim" to serve the software
+    /// simulator
+    #[arg(value_name = "TTY_DEV")]
+    tty_path: PathBuf,
+    /// Address and port to accept connections on
+    #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:7878")]
+    listen: String,
+    /// .toml file describing the board, defaults to the 3x4 prototype
+    #[arg(long, value_name = "PROFILE")]
+    profile: Option<PathBuf>,
+}
+
+enum ForwardError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+/// Copies bytes until `from` closes, either side fails or `done` is set. Sets `done` on the way out
+/// so that the opposite direction stops too.
+fn forward(
+    from: &mut impl Read,
+    to: &mut impl Write,
+    done: &AtomicBool,
+) -> Result<(), ForwardError> {
+    let mut buf = [0; 256];
+    let result = loop {
+        if done.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        let n = match from.read(&mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                continue
+            }
+            Err(e) => break Err(ForwardError::Read(e)),
+        };
+        if let Err(e) = to.write_all(&buf[..n]).and_then(|()| to.flush()) {
+            break Err(ForwardError::Write(e));
+        }
+    };
+    done.store(true, Ordering::Relaxed);
+    result
+}
+
+fn open_tty(tty_path: &Path, profile: &Profile) -> anyhow::Result<TTYPort> {
+    let path = if tty_path == Path::new("auto") {
+        find_device(profile.serial.baud_rate)?.0
+    } else {
+        tty_path.to_owned()
+    };
+    // Linux raises DTR on open by itself, asking for it explicitly fails on pseudo-terminals
+    let builder =
+        serialport::new(path.to_string_lossy(), profile.serial.baud_rate).preserve_dtr_on_open();
+    let mut tty = TTYPort::open(&builder)?;
+    tty.set_timeout(Duration::from_millis(100))?;
+    println!("Forwarding to {}", path.display());
+    Ok(tty)
+}
+
+fn serve_serial(listener: TcpListener, tty: &mut TTYPort) -> anyhow::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let peer = stream.peer_addr()?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+        // Whatever the device sent while nobody was connected belongs to the previous client
+        tty.clear(ClearBuffer::Input)?;
+        println!("{} connected", peer);
+
+        let done = Arc::new(AtomicBool::new(false));
+        let upstream = {
+            let mut tty = tty.try_clone_native()?;
+            let mut stream = stream.try_clone()?;
+            let done = done.clone();
+            thread::spawn(move || forward(&mut tty, &mut stream, &done))
+        };
+        let downstream = forward(&mut stream, tty, &done);
+        let upstream = upstream.join().unwrap_or(Ok(()));
+        println!("{} disconnected", peer);
+
+        // The client going away is expected, the serial port failing is not
+        match (downstream, upstream) {
+            (Err(ForwardError::Write(e)), _) | (_, Err(ForwardError::Read(e))) => {
+                return Err(anyhow!("Lost the serial port: {}", e))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn serve_sim(listener: TcpListener, profile: &Profile) -> anyhow::Result<()> {
+    println!("Serving the firmware simulator");
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let peer = stream.peer_addr()?;
+        stream.set_nodelay(true)?;
+        // Short timeout so PKT_FINISHED goes out close to when the glyph actually ends
+        stream.set_read_timeout(Some(Duration::from_millis(2)))?;
+        println!("{} connected", peer);
+        // Every client gets a freshly booted firmware, like a board that resets on connect
+        if let Err(e) = sim::serve(profile, &mut stream) {
+            println!("{}: {}", peer, e);
+        }
+        println!("{} disconnected", peer);
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let profile = match &cli.profile {
+        Some(path) => Profile::load(path)?,
+        None => Profile::prototype(),
+    };
+    let listener = TcpListener::bind(&cli.listen)?;
+    println!("Listening on {}", listener.local_addr()?);
+    if cli.tty_path == Path::new("sim") {
+        serve_sim(listener, &profile)
+    } else {
+        serve_serial(listener, &mut open_tty(&cli.tty_path, &profile)?)
+    }
+}
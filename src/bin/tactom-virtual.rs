@@ -0,0 +1,49 @@
+use std::{fs, io, os::unix::fs::symlink, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use serialport::{SerialPort, TTYPort};
+use tactom_experiments::{profile::Profile, sim};
+
+/// Emulates the tactom firmware on a pseudo-terminal, so that the experiment CLI can be pointed at
+/// the printed path in place of a real device
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Also make the device reachable through a symlink at this path, which keeps the same name
+    /// across restarts (to try out reconnecting)
+    #[arg(long, value_name = "PATH")]
+    link: Option<PathBuf>,
+    /// .toml file describing the emulated board, defaults to the 3x4 prototype
+    #[arg(long, value_name = "PROFILE")]
+    profile: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let profile = match &cli.profile {
+        Some(path) => Profile::load(path)?,
+        None => Profile::prototype(),
+    };
+    let (mut master, mut slave) = TTYPort::pair()?;
+    slave.set_exclusive(false)?;
+    // Short timeout so PKT_FINISHED goes out close to when the glyph actually ends
+    master.set_timeout(Duration::from_millis(2))?;
+    // The slave end is held open for the lifetime of the program, otherwise reads on the master
+    // start failing as soon as a client disconnects
+    println!(
+        "Virtual tactom device on: {}",
+        slave.name().unwrap_or_default()
+    );
+    if let Some(link) = &cli.link {
+        // A link left behind by a previous run is replaced
+        fs::remove_file(link).or_else(|e| match e.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })?;
+        symlink(slave.name().unwrap_or_default(), link)?;
+        println!("Linked to: {}", link.display());
+    }
+
+    sim::serve(&profile, &mut master)?;
+    Ok(())
+}
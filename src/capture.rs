@@ -0,0 +1,95 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+
+/*
+Capture files are text, one line per write to the device:
+  <microseconds since the capture started> <hex bytes...>
+Lines starting with '#' are comments, the first one identifies the file.
+*/
+const CAPTURE_HEADER: &str = "# tactom capture v1";
+
+/// Tees everything sent to a device into a file, timestamped with the host's monotonic clock
+pub struct Capture {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl Capture {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let unix_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        writeln!(out, "{}", CAPTURE_HEADER)?;
+        writeln!(out, "# started at unix time {}", unix_time)?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        let micros = Instant::now().duration_since(self.start).as_micros();
+        write!(self.out, "{}", micros)?;
+        for byte in bytes {
+            write!(self.out, " {:02x}", byte)?;
+        }
+        writeln!(self.out)?;
+        // Flushed every time so a crash doesn't lose what led up to it
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Every write in a capture file along with when it happened
+pub fn read_capture(path: &Path) -> anyhow::Result<Vec<(Duration, Vec<u8>)>> {
+    let text = fs::read_to_string(path)?;
+    if !text.starts_with(CAPTURE_HEADER) {
+        return Err(anyhow!("{} is not a capture file", path.display()));
+    }
+    let mut writes = vec![];
+    for (line_no, line) in text.lines().enumerate() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let parse_err = || anyhow!("Bad capture line {}: {}", line_no + 1, line);
+        let micros: u64 = fields
+            .next()
+            .and_then(|f| f.parse().ok())
+            .ok_or_else(parse_err)?;
+        let bytes = fields
+            .map(|f| u8::from_str_radix(f, 16).map_err(|_| parse_err()))
+            .collect::<anyhow::Result<Vec<u8>>>()?;
+        writes.push((Duration::from_micros(micros), bytes));
+    }
+    Ok(writes)
+}
+
+/// Loads the bytes of a capture file, a hex dump (whitespace is ignored) or a raw binary file
+pub fn load_bytes(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(CAPTURE_HEADER.as_bytes()) {
+        return Ok(read_capture(path)?
+            .into_iter()
+            .flat_map(|(_, b)| b)
+            .collect());
+    }
+    let digits: Vec<u8> = bytes
+        .iter()
+        .cloned()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_hexdigit) || digits.len() % 2 == 1 {
+        return Ok(bytes);
+    }
+    digits
+        .chunks(2)
+        .map(|pair| Ok(u8::from_str_radix(std::str::from_utf8(pair)?, 16)?))
+        .collect()
+}
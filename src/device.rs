@@ -0,0 +1,572 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use serialport::{SerialPort, TTYPort};
+
+use crate::{
+    capture::Capture,
+    event::{
+        check_glyph, describe_device_error, encode_glyph, encode_packet, encode_play_stored,
+        encode_queued_glyph, encode_store_glyph, Capabilities, DeviceMsg, DeviceMsgDecoder, Ev,
+        StoreSummary, TimeScale, EV_CHORD, FEATURE_ACK, FEATURE_GLYPH_STORE, FEATURE_QUEUE,
+        PKT_CLEAR_STORE, PKT_ECHO, PKT_IDENTIFY, PKT_QUERY_CAPS, PKT_QUERY_STORE, PKT_STOP,
+    },
+};
+
+/// How long an acknowledging firmware may take to make room for the next frame
+const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+/// Boards that reset when the port is opened can take a while to answer
+const CAPS_TIMEOUT: Duration = Duration::from_millis(2000);
+/// How often the reader thread checks whether the device was closed
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Device paths starting with this are the address of a tactom-bridge to connect to, e.g.
+/// "tcp:lab-pc:7878"
+pub const TCP_PREFIX: &str = "tcp:";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceStatus {
+    Idle,
+    Playing,
+    Unknown, // The backend has no way of reading state back
+}
+
+/// When a glyph actually played, in milliseconds since the device was opened or the origin given to
+/// `set_clock_origin`
+#[derive(Clone, Copy, Debug)]
+pub struct Playback {
+    pub onset_ms: u64,
+    pub offset_ms: u64,
+}
+
+/// The serial port stopped working, most likely because the device was unplugged
+#[derive(Debug)]
+pub struct Disconnected;
+
+impl std::fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lost connection to the device")
+    }
+}
+
+impl std::error::Error for Disconnected {}
+
+/// Something that can play glyphs on a participant's palm
+pub trait TactileDevice: Send {
+    /// Queue a glyph for playback. A glyph the device can't play (see `check_glyph`) is refused
+    /// before anything is sent.
+    fn send_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()>;
+
+    /// Queue a glyph to start the moment the one playing ends, see PKT_QUEUE_GLYPH. Only firmware
+    /// with FEATURE_QUEUE can.
+    fn queue_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()>;
+
+    /// Block until the glyph sent or queued last has started, returns its onset on the clock
+    /// Playback uses. None if the device can't report playback.
+    fn wait_onset(&mut self, timeout: Duration) -> anyhow::Result<Option<u64>>;
+
+    /// Keep a glyph on the device under `id`, replacing any glyph already kept there. Only
+    /// firmware with FEATURE_GLYPH_STORE can.
+    fn store_glyph(&mut self, id: u16, glyph: &[Ev]) -> anyhow::Result<()>;
+
+    /// Play the glyph kept under `id` with its times scaled, like `send_glyph` would. An id the
+    /// device doesn't hold is an error from this call.
+    fn play_stored(&mut self, id: u16, scale: TimeScale) -> anyhow::Result<()>;
+
+    /// How many glyphs the device keeps and their checksum, see `store_checksum`
+    fn stored_glyphs(&mut self) -> anyhow::Result<StoreSummary>;
+
+    /// Forget every kept glyph
+    fn clear_stored(&mut self) -> anyhow::Result<()>;
+
+    /// Write bytes to the device as they are, bypassing any bookkeeping. Used to replay captures.
+    fn send_raw(&mut self, bytes: &[u8]) -> anyhow::Result<()>;
+
+    /// Record every byte sent to the device from now on
+    fn set_capture(&mut self, capture: Capture);
+
+    /// Block until the glyph sent last has finished playing.
+    /// Returns None if the device can't report playback, the caller has to guess instead.
+    fn wait_playback(&mut self, timeout: Duration) -> anyhow::Result<Option<Playback>>;
+
+    /// Silence the motors and drop anything still queued
+    fn stop(&mut self) -> anyhow::Result<()>;
+
+    fn status(&mut self) -> anyhow::Result<DeviceStatus>;
+
+    /// Ask the firmware what it can play
+    fn capabilities(&mut self) -> anyhow::Result<Capabilities>;
+
+    /// Measure Playback times from `origin` instead, so that several devices share a clock
+    fn set_clock_origin(&mut self, origin: Instant);
+
+    /// A way to stop the device from another thread while this one is busy, e.g. blocked waiting
+    /// for the participant. None if the device falls silent by itself when the program exits.
+    fn stop_handle(&self) -> Option<StopHandle>;
+}
+
+type Link = Arc<Mutex<Box<dyn Write + Send>>>;
+
+fn write_link(link: &Link, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut link = link.lock().map_err(|_| anyhow!("Device link poisoned"))?;
+    if link.write_all(bytes).and_then(|()| link.flush()).is_err() {
+        return Err(Disconnected.into());
+    }
+    Ok(())
+}
+
+/// Sends PKT_STOP on devices' links without going through the devices. The stop bypasses flow
+/// control and the capture, it's meant for shutting down, not for use during a session.
+#[derive(Clone)]
+pub struct StopHandle {
+    links: Vec<Link>,
+}
+
+impl StopHandle {
+    /// Tries every device even if one of them fails, the first error is returned
+    pub fn stop(&self) -> anyhow::Result<()> {
+        let results: Vec<_> = self
+            .links
+            .iter()
+            .map(|link| write_link(link, &encode_packet(PKT_STOP, &[])))
+            .collect();
+        results.into_iter().collect()
+    }
+
+    /// A handle that stops the devices of both
+    pub fn join(mut self, other: StopHandle) -> StopHandle {
+        self.links.extend(other.links);
+        self
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FlowControl {
+    Unknown, // Capabilities not asked for yet
+    Acked,   // Firmware has FEATURE_ACK, wait for the ack before sending the next frame
+    Unacked, // Firmware doesn't acknowledge, frames are sent back to back
+}
+
+/// The tactom firmware at the other end of a serial port, or of a TCP connection to a bridge that
+/// forwards to one
+pub struct SerialDevice {
+    link: Link,
+    opened: Instant,
+    msgs: Receiver<(Instant, DeviceMsg)>,
+    closed: Arc<AtomicBool>, // Tells the reader thread to exit
+    reader: Option<JoinHandle<()>>,
+    flow: FlowControl,
+    unacked: usize,
+    onset: Option<Instant>,
+    offset: Option<Instant>,
+    error: Option<u8>,
+    identity: Option<String>,
+    capabilities: Option<Capabilities>,
+    store_summary: Option<StoreSummary>,
+    echo: Option<(Instant, Vec<u8>)>, // Latest PKT_ECHO_REPLY and when it arrived
+    capture: Option<Capture>,
+}
+
+impl SerialDevice {
+    pub fn open(path: &Path, baud_rate: u32) -> anyhow::Result<Self> {
+        if let Some(addr) = path.to_str().and_then(|p| p.strip_prefix(TCP_PREFIX)) {
+            return Self::connect(addr);
+        }
+        // Linux raises DTR on open by itself, asking for it explicitly fails on pseudo-terminals
+        let builder = serialport::new(path.to_string_lossy(), baud_rate).preserve_dtr_on_open();
+        let tty = TTYPort::open(&builder)?;
+        let mut reader = tty.try_clone_native()?;
+        reader.set_timeout(READ_TIMEOUT)?;
+        Ok(Self::from_link(Box::new(tty), reader))
+    }
+
+    /// Connect to a tactom-bridge listening on `addr`
+    pub fn connect(addr: &str) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        // Glyphs are small and latency matters more than throughput
+        stream.set_nodelay(true)?;
+        let reader = stream.try_clone()?;
+        reader.set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(Self::from_link(Box::new(stream), reader))
+    }
+
+    fn from_link(link: Box<dyn Write + Send>, reader: impl Read + Send + 'static) -> Self {
+        let closed = Arc::new(AtomicBool::new(false));
+        let (msgs, reader) = spawn_reader(reader, closed.clone());
+        Self {
+            link: Arc::new(Mutex::new(link)),
+            opened: Instant::now(),
+            msgs,
+            closed,
+            reader: Some(reader),
+            flow: FlowControl::Unknown,
+            unacked: 0,
+            onset: None,
+            offset: None,
+            error: None,
+            identity: None,
+            capabilities: None,
+            store_summary: None,
+            echo: None,
+            capture: None,
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.record(bytes)?;
+        }
+        write_link(&self.link, bytes)
+    }
+
+    /// Takes over what has to outlive a reconnect from the device this one replaces: the capture,
+    /// the clock playback times are measured against and the link stop handles write to
+    pub(crate) fn carry_over(&mut self, old: &mut SerialDevice) {
+        self.opened = old.opened;
+        self.capture = old.capture.take();
+        // Move the new connection into the shared slot, so that handles given out before the
+        // reconnect stop the device on its new connection
+        if let (Ok(mut new), Ok(mut shared)) = (self.link.lock(), old.link.lock()) {
+            std::mem::swap(&mut *new, &mut *shared);
+        }
+        self.link = old.link.clone();
+    }
+
+    fn handle(&mut self, at: Instant, msg: DeviceMsg) {
+        match msg {
+            DeviceMsg::Ack => self.unacked = self.unacked.saturating_sub(1),
+            DeviceMsg::Error(code) => {
+                self.unacked = self.unacked.saturating_sub(1);
+                self.error = Some(code);
+            }
+            // Only count reports that come after the latest frame was acknowledged, anything
+            // earlier belongs to a previous glyph
+            DeviceMsg::Started if self.unacked == 0 => self.onset = Some(at),
+            DeviceMsg::Finished if self.onset.is_some() => self.offset = Some(at),
+            DeviceMsg::Started | DeviceMsg::Finished => {}
+            DeviceMsg::Identity(id) => self.identity = Some(id),
+            DeviceMsg::Capabilities(caps) => self.capabilities = Some(caps),
+            DeviceMsg::StoreSummary(summary) => self.store_summary = Some(summary),
+            DeviceMsg::Echo(payload) => self.echo = Some((at, payload)),
+        }
+    }
+
+    /// Ask the firmware to identify itself, None if nothing answered in time
+    pub fn identify(&mut self, timeout: Duration) -> anyhow::Result<Option<String>> {
+        self.identity = None;
+        self.write_bytes(&encode_packet(PKT_IDENTIFY, &[]))?;
+        self.wait_until(timeout, |d| d.identity.is_some())?;
+        Ok(self.identity.clone())
+    }
+
+    /// Time from writing a PKT_ECHO until its reply arrived, None if nothing came back in time.
+    /// Only firmware with FEATURE_ECHO answers.
+    pub fn echo(&mut self, payload: &[u8], timeout: Duration) -> anyhow::Result<Option<Duration>> {
+        self.echo = None;
+        let sent = Instant::now();
+        self.write_bytes(&encode_packet(PKT_ECHO, payload))?;
+        let answered = |d: &Self| d.echo.as_ref().is_some_and(|(_, p)| p == payload);
+        if !self.wait_until(timeout, answered)? {
+            return Ok(None);
+        }
+        Ok(self.echo.as_ref().map(|(at, _)| at.duration_since(sent)))
+    }
+
+    /// Handle messages until `done` holds, false if the deadline passed first
+    fn wait_until(
+        &mut self,
+        timeout: Duration,
+        done: impl Fn(&Self) -> bool,
+    ) -> anyhow::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        while !done(self) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.msgs.recv_timeout(remaining) {
+                Ok((at, msg)) => self.handle(at, msg),
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => return Err(Disconnected.into()),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether the firmware acknowledges, asking for its capabilities if nobody did yet
+    fn flow(&mut self) -> anyhow::Result<FlowControl> {
+        if self.flow == FlowControl::Unknown {
+            self.capabilities()?;
+        }
+        Ok(self.flow)
+    }
+
+    /// Sends a packet the firmware acknowledges and that replaces whatever was playing
+    fn send_frame(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        let acked = self.flow()? == FlowControl::Acked;
+        if acked && !self.wait_until(ACK_TIMEOUT, |d| d.unacked == 0)? {
+            return Err(anyhow!("Device did not acknowledge the previous frame"));
+        }
+        self.write_bytes(packet)?;
+        if acked {
+            self.unacked += 1;
+        }
+        self.onset = None;
+        self.offset = None;
+        self.error = None;
+        Ok(())
+    }
+
+    /// Sends a packet the firmware acknowledges without playing anything, and waits for the
+    /// acknowledgement so that a rejection is reported by the call that caused it
+    fn send_command(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        if self.flow()? == FlowControl::Unacked {
+            return self.write_bytes(packet);
+        }
+        if !self.wait_until(ACK_TIMEOUT, |d| d.unacked == 0)? {
+            return Err(anyhow!("Device did not acknowledge the previous frame"));
+        }
+        self.write_bytes(packet)?;
+        self.unacked += 1;
+        self.error = None;
+        self.wait_ack()
+    }
+
+    /// Waits for the firmware to acknowledge the latest packet, an error if it rejected it
+    fn wait_ack(&mut self) -> anyhow::Result<()> {
+        if !self.wait_until(ACK_TIMEOUT, |d| d.unacked == 0)? {
+            return Err(anyhow!("Device did not acknowledge the command"));
+        }
+        match self.error.take() {
+            Some(code) => Err(anyhow!(
+                "Device rejected the command: {}",
+                describe_device_error(code)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Only once the capabilities have been asked for and list the feature
+    fn require(&self, feature: u8, missing: &str) -> anyhow::Result<()> {
+        if self.capabilities.is_some_and(|caps| caps.has(feature)) {
+            Ok(())
+        } else {
+            Err(anyhow!("{}", missing))
+        }
+    }
+
+    /// Until the firmware has been asked for its capabilities, motors are only checked against
+    /// the largest board the protocol allows
+    fn check_before_send(&self, glyph: &[Ev]) -> anyhow::Result<()> {
+        match self.capabilities {
+            Some(caps) => caps.check_glyph(glyph),
+            None => check_glyph(glyph, EV_CHORD),
+        }
+        .map_err(|e| anyhow!("Invalid glyph, {}", e))
+    }
+
+    /// Milliseconds from opening the device to `t`, the clock Playback is measured on
+    fn since_opened(&self, t: Instant) -> u64 {
+        t.duration_since(self.opened).as_millis() as u64
+    }
+
+    /// When the reports of the playback `wait_playback` returned last arrived, finer than the
+    /// whole milliseconds in Playback
+    pub fn playback_times(&self) -> Option<(Instant, Instant)> {
+        self.onset.zip(self.offset)
+    }
+}
+
+impl Drop for SerialDevice {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        // The reader holds its own handle on the port, wait for it to let go so that the port can
+        // be opened again straight away
+        if let Some(reader) = self.reader.take() {
+            reader.join().unwrap_or(());
+        }
+    }
+}
+
+/// Reads firmware messages on a separate thread so that they are timestamped as they arrive. The
+/// reader must time out every READ_TIMEOUT, the thread exits once the link closes or fails.
+fn spawn_reader(
+    mut reader: impl Read + Send + 'static,
+    closed: Arc<AtomicBool>,
+) -> (Receiver<(Instant, DeviceMsg)>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let mut decoder = DeviceMsgDecoder::default();
+        let mut buf = [0; 64];
+        while !closed.load(Ordering::Relaxed) {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                // Serial ports report a timeout as TimedOut, sockets as WouldBlock
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    continue
+                }
+                Err(_) => break,
+            };
+            let now = Instant::now();
+            for &byte in &buf[..n] {
+                if let Some(msg) = decoder.push(byte) {
+                    if tx.send((now, msg)).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    (rx, handle)
+}
+
+impl TactileDevice for SerialDevice {
+    fn send_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.check_before_send(glyph)?;
+        self.send_frame(&encode_glyph(glyph))
+    }
+
+    /// Only once the capabilities have been asked for and say the firmware can queue
+    fn queue_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.require(FEATURE_QUEUE, "The firmware can't queue glyphs")?;
+        self.check_before_send(glyph)?;
+        self.send_frame(&encode_queued_glyph(glyph))
+    }
+
+    fn store_glyph(&mut self, id: u16, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.require(FEATURE_GLYPH_STORE, "The firmware can't store glyphs")?;
+        self.check_before_send(glyph)?;
+        self.send_command(&encode_store_glyph(id, glyph))
+    }
+
+    /// Waits for the acknowledgement, so that a glyph id the firmware doesn't hold is reported
+    /// here, like a rejected command
+    fn play_stored(&mut self, id: u16, scale: TimeScale) -> anyhow::Result<()> {
+        self.require(FEATURE_GLYPH_STORE, "The firmware can't store glyphs")?;
+        self.send_frame(&encode_play_stored(id, scale))?;
+        if self.flow == FlowControl::Acked {
+            self.wait_ack()?;
+        }
+        Ok(())
+    }
+
+    fn stored_glyphs(&mut self) -> anyhow::Result<StoreSummary> {
+        self.require(FEATURE_GLYPH_STORE, "The firmware can't store glyphs")?;
+        self.store_summary = None;
+        self.write_bytes(&encode_packet(PKT_QUERY_STORE, &[]))?;
+        self.wait_until(CAPS_TIMEOUT, |d| d.store_summary.is_some())?;
+        self.store_summary
+            .ok_or_else(|| anyhow!("Device did not say which glyphs it stores"))
+    }
+
+    fn clear_stored(&mut self) -> anyhow::Result<()> {
+        self.require(FEATURE_GLYPH_STORE, "The firmware can't store glyphs")?;
+        self.send_command(&encode_packet(PKT_CLEAR_STORE, &[]))
+    }
+
+    fn wait_onset(&mut self, timeout: Duration) -> anyhow::Result<Option<u64>> {
+        if self.flow != FlowControl::Acked {
+            return Ok(None);
+        }
+        self.wait_until(timeout, |d| d.onset.is_some() || d.error.is_some())?;
+        if let Some(code) = self.error {
+            return Err(anyhow!(
+                "Device rejected glyph: {}",
+                describe_device_error(code)
+            ));
+        }
+        match self.onset {
+            Some(onset) => Ok(Some(self.since_opened(onset))),
+            None => Err(anyhow!("Device did not report the start of playback")),
+        }
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.write_bytes(bytes)
+    }
+
+    fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    fn wait_playback(&mut self, timeout: Duration) -> anyhow::Result<Option<Playback>> {
+        if self.flow != FlowControl::Acked {
+            return Ok(None);
+        }
+        let finished = self.wait_until(timeout, |d| d.offset.is_some() || d.error.is_some())?;
+        if let Some(code) = self.error {
+            return Err(anyhow!(
+                "Device rejected glyph: {}",
+                describe_device_error(code)
+            ));
+        }
+        match (finished, self.onset, self.offset) {
+            (true, Some(onset), Some(offset)) => Ok(Some(Playback {
+                onset_ms: self.since_opened(onset),
+                offset_ms: self.since_opened(offset),
+            })),
+            _ => Err(anyhow!("Device did not report the end of playback")),
+        }
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.send_frame(&encode_packet(PKT_STOP, &[]))
+    }
+
+    fn status(&mut self) -> anyhow::Result<DeviceStatus> {
+        if self.flow != FlowControl::Acked {
+            return Ok(DeviceStatus::Unknown);
+        }
+        loop {
+            match self.msgs.try_recv() {
+                Ok((at, msg)) => self.handle(at, msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(Disconnected.into()),
+            }
+        }
+        if self.onset.is_some() && self.offset.is_none() {
+            Ok(DeviceStatus::Playing)
+        } else {
+            Ok(DeviceStatus::Idle)
+        }
+    }
+
+    fn capabilities(&mut self) -> anyhow::Result<Capabilities> {
+        self.capabilities = None;
+        self.write_bytes(&encode_packet(PKT_QUERY_CAPS, &[]))?;
+        self.wait_until(CAPS_TIMEOUT, |d| d.capabilities.is_some())?;
+        let caps = self.capabilities.ok_or_else(|| {
+            anyhow!("Device did not answer the capability query, its firmware is too old")
+        })?;
+        self.flow = if caps.has(FEATURE_ACK) {
+            FlowControl::Acked
+        } else {
+            FlowControl::Unacked
+        };
+        Ok(caps)
+    }
+
+    fn set_clock_origin(&mut self, origin: Instant) {
+        self.opened = origin;
+    }
+
+    fn stop_handle(&self) -> Option<StopHandle> {
+        Some(StopHandle {
+            links: vec![self.link.clone()],
+        })
+    }
+}
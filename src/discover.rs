@@ -0,0 +1,97 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::anyhow;
+use serialport::{SerialPortType, UsbPortInfo};
+
+use crate::{device::SerialDevice, event::IDENTITY_PREFIX};
+
+/// USB vendor and product ids of the boards the tactom firmware has been flashed to, None matches
+/// any product of that vendor
+pub const TACTOM_USB_IDS: &[(u16, Option<u16>)] = &[
+    (0x2341, None),         // Arduino
+    (0x2E8A, Some(0x000A)), // Raspberry Pi Pico
+    (0x16C0, Some(0x0483)), // Teensy
+    (0x1A86, Some(0x7523)), // CH340 USB serial adapter
+];
+
+/// Boards that reset when the port is opened can take a while to answer
+const IDENTIFY_TIMEOUT: Duration = Duration::from_millis(2000);
+
+pub struct Candidate {
+    pub path: PathBuf,
+    pub usb: Option<UsbPortInfo>,
+    pub identity: Option<String>, // None if it wasn't asked or didn't answer
+}
+
+impl Candidate {
+    pub fn matches_usb_id(&self) -> bool {
+        self.usb.as_ref().is_some_and(|usb| {
+            TACTOM_USB_IDS
+                .iter()
+                .any(|&(vid, pid)| usb.vid == vid && pid.is_none_or(|pid| usb.pid == pid))
+        })
+    }
+
+    pub fn is_tactom(&self) -> bool {
+        self.identity
+            .as_ref()
+            .is_some_and(|id| id.starts_with(IDENTITY_PREFIX))
+    }
+}
+
+fn ports() -> anyhow::Result<Vec<Candidate>> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .map(|port| Candidate {
+            path: PathBuf::from(port.port_name),
+            usb: match port.port_type {
+                SerialPortType::UsbPort(usb) => Some(usb),
+                _ => None,
+            },
+            identity: None,
+        })
+        .collect())
+}
+
+/// Opens a port and asks it to identify, the device is kept open if it answered
+fn probe(candidate: &mut Candidate, baud_rate: u32) -> Option<SerialDevice> {
+    let mut dev = SerialDevice::open(&candidate.path, baud_rate).ok()?;
+    candidate.identity = dev.identify(IDENTIFY_TIMEOUT).ok()?;
+    Some(dev)
+}
+
+/// Every serial port on the system, the ones with a matching USB id are asked to identify
+pub fn list_candidates(baud_rate: u32) -> anyhow::Result<Vec<Candidate>> {
+    let mut candidates = ports()?;
+    for candidate in candidates.iter_mut().filter(|c| c.matches_usb_id()) {
+        probe(candidate, baud_rate);
+    }
+    Ok(candidates)
+}
+
+/// Finds the one connected tactom device and opens it
+pub fn find_device(baud_rate: u32) -> anyhow::Result<(PathBuf, SerialDevice)> {
+    let mut found = vec![];
+    for mut candidate in ports()?.into_iter().filter(Candidate::matches_usb_id) {
+        if let Some(dev) = probe(&mut candidate, baud_rate) {
+            if candidate.is_tactom() {
+                found.push((candidate.path, dev));
+            }
+        }
+    }
+    match found.len() {
+        0 => Err(anyhow!(
+            "No tactom device found, check the `devices` subcommand"
+        )),
+        1 => Ok(found.remove(0)),
+        _ => Err(anyhow!(
+            "Found {} tactom devices, pass the one to use instead of \"auto\": {}",
+            found.len(),
+            found
+                .iter()
+                .map(|(path, _)| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
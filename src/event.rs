@@ -1,61 +1,1232 @@
-use std::{io::Write, iter, thread::sleep, time::Duration};
+use std::collections::BTreeMap;
 
-use serialport::TTYPort;
+/*
+Everything on the wire, in both directions, is a packet framed with SLIP:
+  END, escaped([version, kind, payload..., crc_hi, crc_lo]), END
+END and ESC bytes inside a packet are replaced by ESC ESC_END and ESC ESC_ESC, so END only ever
+marks a packet boundary. The CRC is CRC-16/CCITT-FALSE over version, kind and payload.
+*/
+pub const SLIP_END: u8 = 0xC0;
+pub const SLIP_ESC: u8 = 0xDB;
+pub const SLIP_ESC_END: u8 = 0xDC;
+pub const SLIP_ESC_ESC: u8 = 0xDD;
+
+// Version 1 was the unescaped RAW_ENTER/RAW_EXIT framing, version 2 ended glyphs with event type 12
+// which left no room for boards with more than 12 motors, version 3 events had no intensity or
+// pulse length, version 4 had no chords, version 5 had no stop command, version 6 had no capability
+// handshake
+pub const PROTOCOL_VERSION: u8 = 7;
+
+// Host to firmware
+/// Payload is (type, ms_hi, ms_lo, intensity, pulse_ms_hi, pulse_ms_lo) for each event, chords are
+/// followed by (mask_len, mask...) with motor i in bit i % 8 of mask byte i / 8
+pub const PKT_GLYPH: u8 = 0x01;
+/// Asks the firmware for a PKT_IDENTITY, not acknowledged
+pub const PKT_IDENTIFY: u8 = 0x02;
+/// Silences the motors and drops the queued glyph, no payload
+pub const PKT_STOP: u8 = 0x03;
+/// Asks the firmware for a PKT_CAPABILITIES, not acknowledged
+pub const PKT_QUERY_CAPS: u8 = 0x04;
+/// Payload is sent straight back in a PKT_ECHO_REPLY, not acknowledged
+pub const PKT_ECHO: u8 = 0x05;
+/// Payload as PKT_GLYPH. Instead of cutting the playing glyph short, it starts the moment that one
+/// reaches its EndGlyph. Only one glyph waits, a newer one replaces it, and PKT_GLYPH drops it.
+pub const PKT_QUEUE_GLYPH: u8 = 0x06;
+/// Payload is (id_hi, id_lo) followed by a PKT_GLYPH payload. Keeps the glyph under that id, in
+/// place of whatever had it, until PKT_CLEAR_STORE. Rejected with ERR_STORE_FULL when there's no
+/// room left.
+pub const PKT_STORE_GLYPH: u8 = 0x07;
+/// Payload is (id_hi, id_lo, num_hi, num_lo, den_hi, den_lo). Plays a stored glyph as if it had
+/// come in a PKT_GLYPH with every time multiplied by num / den, see TimeScale.
+pub const PKT_PLAY_STORED: u8 = 0x08;
+/// Asks the firmware for a PKT_STORE_SUMMARY, not acknowledged
+pub const PKT_QUERY_STORE: u8 = 0x09;
+/// Forgets every stored glyph, no payload
+pub const PKT_CLEAR_STORE: u8 = 0x0A;
+
+// Firmware to host
+/// A packet has left the receive buffer
+pub const PKT_ACK: u8 = 0x81;
+/// The acknowledged glyph has started playing
+pub const PKT_STARTED: u8 = 0x82;
+/// The playing glyph reached its EndGlyph
+pub const PKT_FINISHED: u8 = 0x83;
+/// A packet was rejected, sent instead of PKT_ACK, payload is the code
+pub const PKT_ERROR: u8 = 0x84;
+/// Payload is a UTF-8 description starting with IDENTITY_PREFIX
+pub const PKT_IDENTITY: u8 = 0x85;
+
+/// Payload is (protocol_version, motor_count, max_glyph_len_hi, max_glyph_len_lo, features). Both
+/// capability packets keep their kind and layout from version 7 on and are accepted whatever
+/// version their header says, so that a host can find out which version the firmware speaks.
+pub const PKT_CAPABILITIES: u8 = 0x86;
+/// Payload is that of the PKT_ECHO it answers
+pub const PKT_ECHO_REPLY: u8 = 0x87;
+/// Payload is (count_hi, count_lo, checksum_hi, checksum_lo), see store_checksum
+pub const PKT_STORE_SUMMARY: u8 = 0x88;
+
+pub const IDENTITY_PREFIX: &str = "tactom";
 
-const RAW_ENTER: u8 = 0xC0;
-const RAW_EXIT: u8 = 0xF5;
+pub const ERR_MALFORMED_FRAME: u8 = 1;
+pub const ERR_BAD_EVENT_TYPE: u8 = 2;
+pub const ERR_BAD_CRC: u8 = 3;
+pub const ERR_BAD_VERSION: u8 = 4;
+pub const ERR_UNKNOWN_COMMAND: u8 = 5;
+pub const ERR_MISSING_END_GLYPH: u8 = 6;
+pub const ERR_GLYPH_TOO_LONG: u8 = 7;
+pub const ERR_STORE_FULL: u8 = 8;
+pub const ERR_NO_STORED_GLYPH: u8 = 9;
+
+// Bits of the features byte in PKT_CAPABILITIES, a firmware only has to handle what it advertises
+pub const FEATURE_CHORDS: u8 = 0x01;
+pub const FEATURE_INTENSITY: u8 = 0x02; // Without it every pulse is played at full intensity
+pub const FEATURE_PULSE_MS: u8 = 0x04; // Without it every pulse has the firmware's fixed length
+pub const FEATURE_ECHO: u8 = 0x08; // Answers PKT_ECHO
+pub const FEATURE_QUEUE: u8 = 0x10; // Takes PKT_QUEUE_GLYPH
+pub const FEATURE_GLYPH_STORE: u8 = 0x20; // Takes PKT_STORE_GLYPH and the commands that go with it
+pub const FEATURE_ACK: u8 = 0x40; // Answers every glyph and command with PKT_ACK or PKT_ERROR
+
+/// Event type byte of a chord, followed by the length of its motor mask and the mask itself
+pub const EV_CHORD: u8 = 0xFE;
+/// Event type byte that ends a glyph
+pub const EV_END_GLYPH: u8 = 0xFF;
 
 /*
-Placement of motors on the palm, with the plam facing the table:
-Numbers represent the index of the motor
-  Fingers
-  0  1  2  3
-  4  5  6  7
-  8  9 10 11
-  Wrist
+Event type bytes below EV_CHORD play the motor with that index, how many motors there are and
+where they sit on the palm is described by the device profile (see profile.rs).
 */
-
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum EvType {
-    _Go0 = 0, // Play motor with index "GO<index>"
-    _Go1,
-    _Go2,
-    _Go3,
-    _Go4,
-    _Go5,
-    _Go6,
-    _Go7,
-    _Go8,
-    _Go9,
-    _Go10,
-    _Go11,
-    EndGlyph, // Denote the end of a glyph
-}
-
-#[derive(Clone, Copy, Debug)]
+    Motor(u8),       // Play the motor with this index
+    Chord(MotorSet), // Play every motor in the set at once
+    EndGlyph,        // Denote the end of a glyph
+}
+
+impl EvType {
+    /// Byte the type is sent as
+    pub fn code(&self) -> u8 {
+        match self {
+            EvType::Motor(motor) => *motor,
+            EvType::Chord(_) => EV_CHORD,
+            EvType::EndGlyph => EV_END_GLYPH,
+        }
+    }
+}
+
+/// Bytes per event in a PKT_GLYPH payload, not counting a chord's mask
+pub const EVENT_LEN: usize = 6;
+
+/// A set of motors, covers every event type byte that can be a motor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MotorSet([u64; 4]);
+
+/// Longest chord mask on the wire
+pub const MAX_MASK_LEN: usize = 32;
+
+impl MotorSet {
+    pub fn insert(&mut self, motor: u8) {
+        self.0[motor as usize / 64] |= 1 << (motor % 64);
+    }
+
+    pub fn contains(&self, motor: u8) -> bool {
+        self.0[motor as usize / 64] & (1 << (motor % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(|&motor| self.contains(motor))
+    }
+
+    /// Wire format mask, without trailing zero bytes
+    pub fn to_mask(self) -> Vec<u8> {
+        let mut mask: Vec<u8> = self.0.iter().flat_map(|word| word.to_le_bytes()).collect();
+        while mask.last() == Some(&0) {
+            mask.pop();
+        }
+        mask
+    }
+
+    pub fn from_mask(mask: &[u8]) -> Self {
+        let mut set = Self::default();
+        for (i, byte) in mask.iter().enumerate().take(MAX_MASK_LEN) {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    set.insert((i * 8 + bit) as u8);
+                }
+            }
+        }
+        set
+    }
+}
+
+impl FromIterator<u8> for MotorSet {
+    fn from_iter<I: IntoIterator<Item = u8>>(motors: I) -> Self {
+        let mut set = Self::default();
+        for motor in motors {
+            set.insert(motor);
+        }
+        set
+    }
+}
+
+pub const FULL_INTENSITY: u8 = 255;
+/// Pulse length that leaves it to the firmware, which then uses its fixed length
+pub const DEFAULT_PULSE_MS: u16 = 0;
+
+/// An event of a glyph. Whether the motors it plays exist is only known once the glyph is checked
+/// against a board, see `check_glyph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Ev {
     pub ms_time: u16,
-    pub ev_type: u8,
+    pub ev_type: EvType,
+    pub intensity: u8, // 0 is off, FULL_INTENSITY is as strong as the motor goes
+    pub pulse_ms: u16, // How long the motor stays on, ignored for EndGlyph
 }
 
 impl Ev {
-    pub fn new(ms_time: u16, ev_type: u8) -> Self {
-        Self { ms_time, ev_type }
+    fn new(ms_time: u16, ev_type: EvType) -> Self {
+        Self {
+            ms_time,
+            ev_type,
+            intensity: FULL_INTENSITY,
+            pulse_ms: DEFAULT_PULSE_MS,
+        }
+    }
+
+    /// A full strength pulse of the firmware's default length
+    pub fn motor(ms_time: u16, motor: u8) -> Self {
+        Self::new(ms_time, EvType::Motor(motor))
+    }
+
+    /// Several motors starting together, as full strength pulses of the firmware's default length
+    pub fn chord(ms_time: u16, motors: &[u8]) -> Self {
+        Self::new(ms_time, EvType::Chord(motors.iter().copied().collect()))
+    }
+
+    /// The end of a glyph, it has finished playing at `ms_time`
+    pub fn end(ms_time: u16) -> Self {
+        Self::new(ms_time, EvType::EndGlyph)
+    }
+
+    /// Bytes the event takes up in a PKT_GLYPH payload
+    pub fn payload_len(&self) -> usize {
+        match self.ev_type {
+            EvType::Chord(motors) => EVENT_LEN + 1 + motors.to_mask().len(),
+            _ => EVENT_LEN,
+        }
+    }
+
+    /// Motors this event plays
+    pub fn motors(&self) -> MotorSet {
+        match self.ev_type {
+            EvType::Motor(motor) => MotorSet::from_iter([motor]),
+            EvType::Chord(motors) => motors,
+            EvType::EndGlyph => MotorSet::default(),
+        }
+    }
+
+    pub fn with_intensity(self, intensity: u8) -> Self {
+        Self { intensity, ..self }
+    }
+
+    pub fn with_pulse_ms(self, pulse_ms: u16) -> Self {
+        Self { pulse_ms, ..self }
+    }
+}
+
+impl std::fmt::Display for Ev {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ev_type {
+            EvType::EndGlyph => return write!(f, "end of glyph"),
+            EvType::Chord(motors) => {
+                let motors: Vec<String> = motors.iter().map(|m| m.to_string()).collect();
+                write!(f, "motors {}", motors.join(" "))?;
+            }
+            EvType::Motor(motor) => write!(f, "motor {}", motor)?,
+        }
+        write!(f, ", intensity {}, ", self.intensity)?;
+        match self.pulse_ms {
+            DEFAULT_PULSE_MS => write!(f, "default pulse"),
+            ms => write!(f, "{} ms pulse", ms),
+        }
     }
 }
 
-pub fn queue_events_as_raw(events: &[Ev], tty: &mut TTYPort) -> anyhow::Result<()> {
-    let bytes_iter = events
+/// CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub kind: u8,
+    pub payload: Vec<u8>,
+}
+
+pub fn encode_packet(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![PROTOCOL_VERSION, kind];
+    body.extend_from_slice(payload);
+    body.extend(crc16(&body).to_be_bytes());
+
+    let mut out = Vec::with_capacity(body.len() + 2);
+    out.push(SLIP_END);
+    for byte in body {
+        match byte {
+            SLIP_END => out.extend([SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => out.extend([SLIP_ESC, SLIP_ESC_ESC]),
+            b => out.push(b),
+        }
+    }
+    out.push(SLIP_END);
+    out
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketError {
+    TooShort,
+    BadEscape,
+    BadCrc,
+    BadVersion(u8),
+}
+
+impl std::fmt::Display for PacketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PacketError::TooShort => write!(f, "packet too short"),
+            PacketError::BadEscape => write!(f, "invalid escape sequence"),
+            PacketError::BadCrc => write!(f, "checksum mismatch"),
+            PacketError::BadVersion(v) => {
+                write!(f, "protocol version {} (expected {})", v, PROTOCOL_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PacketError {}
+
+/// Splits a byte stream into packets, bytes can be pushed in as they arrive
+#[derive(Default)]
+pub struct PacketDecoder {
+    buf: Vec<u8>,
+    escaped: bool,
+    bad_escape: bool,
+}
+
+impl PacketDecoder {
+    /// Whether bytes of an unfinished packet have been pushed
+    pub fn in_packet(&self) -> bool {
+        !self.buf.is_empty() || self.escaped || self.bad_escape
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Result<Packet, PacketError>> {
+        match (self.escaped, byte) {
+            (_, SLIP_END) => {
+                self.escaped = false;
+                let body = std::mem::take(&mut self.buf);
+                let bad_escape = std::mem::replace(&mut self.bad_escape, false);
+                if body.is_empty() {
+                    // Back to back ENDs between packets
+                    return None;
+                }
+                if bad_escape {
+                    return Some(Err(PacketError::BadEscape));
+                }
+                return Some(parse_packet_body(&body));
+            }
+            (false, SLIP_ESC) => self.escaped = true,
+            (false, b) => self.buf.push(b),
+            (true, SLIP_ESC_END) => {
+                self.escaped = false;
+                self.buf.push(SLIP_END);
+            }
+            (true, SLIP_ESC_ESC) => {
+                self.escaped = false;
+                self.buf.push(SLIP_ESC);
+            }
+            (true, b) => {
+                self.escaped = false;
+                self.bad_escape = true;
+                self.buf.push(b);
+            }
+        }
+        None
+    }
+}
+
+fn parse_packet_body(body: &[u8]) -> Result<Packet, PacketError> {
+    if body.len() < 4 {
+        return Err(PacketError::TooShort);
+    }
+    let (data, crc) = body.split_at(body.len() - 2);
+    if crc16(data).to_be_bytes() != crc {
+        return Err(PacketError::BadCrc);
+    }
+    if data[0] != PROTOCOL_VERSION && !matches!(data[1], PKT_QUERY_CAPS | PKT_CAPABILITIES) {
+        return Err(PacketError::BadVersion(data[0]));
+    }
+    Ok(Packet {
+        kind: data[1],
+        payload: data[2..].to_vec(),
+    })
+}
+
+pub fn encode_glyph(events: &[Ev]) -> Vec<u8> {
+    encode_packet(PKT_GLYPH, &glyph_payload(events))
+}
+
+/// A glyph that waits for the playing one to end, see PKT_QUEUE_GLYPH
+pub fn encode_queued_glyph(events: &[Ev]) -> Vec<u8> {
+    encode_packet(PKT_QUEUE_GLYPH, &glyph_payload(events))
+}
+
+/// A glyph the way PKT_GLYPH carries it
+pub fn glyph_payload(events: &[Ev]) -> Vec<u8> {
+    events
         .iter()
-        .flat_map(|ev| iter::once(ev.ev_type).chain(ev.ms_time.to_be_bytes()));
-    let bytes: Vec<u8> = iter::once(RAW_ENTER)
-        .chain(bytes_iter)
-        .chain(iter::once(RAW_EXIT))
-        .collect();
-    for byte in bytes {
-        tty.write_all(&[byte])?;
-        tty.flush()?;
-        sleep(Duration::from_millis(1)); // TODO fix this
+        .flat_map(|ev| {
+            let [ms_hi, ms_lo] = ev.ms_time.to_be_bytes();
+            let [pulse_hi, pulse_lo] = ev.pulse_ms.to_be_bytes();
+            let mut bytes = vec![
+                ev.ev_type.code(),
+                ms_hi,
+                ms_lo,
+                ev.intensity,
+                pulse_hi,
+                pulse_lo,
+            ];
+            if let EvType::Chord(motors) = ev.ev_type {
+                let mask = motors.to_mask();
+                bytes.push(mask.len() as u8);
+                bytes.extend(mask);
+            }
+            bytes
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GlyphError {
+    /// Payload isn't a whole number of events
+    Truncated {
+        len: usize,
+    },
+    NoSuchMotor {
+        event: usize,
+        motor: u8,
+        motor_count: u8,
+    },
+    /// Empty, mask too long or plays a motor the board doesn't have
+    BadChord {
+        event: usize,
+    },
+    TimeGoesBack {
+        event: usize,
+        ms_time: u16,
+        previous_ms: u16,
+    },
+    /// An EndGlyph with more events after it
+    EarlyEndGlyph {
+        event: usize,
+    },
+    MissingEndGlyph,
+    /// Payload bytes, more than the firmware can buffer
+    TooLong {
+        len: usize,
+        max: usize,
+    },
+    ChordsUnsupported {
+        event: usize,
+    },
+}
+
+impl std::fmt::Display for GlyphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlyphError::Truncated { len } => write!(
+                f,
+                "truncated frame, {} bytes ends part way through an event",
+                len
+            ),
+            GlyphError::NoSuchMotor {
+                event,
+                motor,
+                motor_count,
+            } => write!(
+                f,
+                "event {} plays motor {}, the board only has {} motors",
+                event, motor, motor_count
+            ),
+            GlyphError::BadChord { event } => write!(f, "bad chord in event {}", event),
+            GlyphError::TimeGoesBack {
+                event,
+                ms_time,
+                previous_ms,
+            } => write!(
+                f,
+                "event {} at {} ms comes before the event ahead of it, at {} ms",
+                event, ms_time, previous_ms
+            ),
+            GlyphError::EarlyEndGlyph { event } => {
+                write!(f, "EndGlyph in event {} isn't the last event", event)
+            }
+            GlyphError::MissingEndGlyph => write!(f, "missing EndGlyph"),
+            GlyphError::TooLong { len, max } => {
+                write!(f, "{} byte frame, the firmware takes at most {}", len, max)
+            }
+            GlyphError::ChordsUnsupported { event } => {
+                write!(
+                    f,
+                    "chord in event {}, the firmware can't play chords",
+                    event
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GlyphError {}
+
+impl GlyphError {
+    /// Code the firmware reports when it rejects a glyph for this reason
+    pub fn device_error_code(&self) -> u8 {
+        match self {
+            GlyphError::Truncated { .. }
+            | GlyphError::TimeGoesBack { .. }
+            | GlyphError::EarlyEndGlyph { .. } => ERR_MALFORMED_FRAME,
+            GlyphError::NoSuchMotor { .. }
+            | GlyphError::BadChord { .. }
+            | GlyphError::ChordsUnsupported { .. } => ERR_BAD_EVENT_TYPE,
+            GlyphError::MissingEndGlyph => ERR_MISSING_END_GLYPH,
+            GlyphError::TooLong { .. } => ERR_GLYPH_TOO_LONG,
+        }
+    }
+}
+
+/// Checks that a glyph only plays motors a board with `motor_count` motors has, that its times
+/// never go back and that it ends with its only EndGlyph
+pub fn check_glyph(glyph: &[Ev], motor_count: u8) -> Result<(), GlyphError> {
+    let mut previous_ms = 0;
+    for (event, ev) in glyph.iter().enumerate() {
+        if ev.ms_time < previous_ms {
+            return Err(GlyphError::TimeGoesBack {
+                event,
+                ms_time: ev.ms_time,
+                previous_ms,
+            });
+        }
+        previous_ms = ev.ms_time;
+        match ev.ev_type {
+            EvType::Motor(motor) if motor >= motor_count => {
+                return Err(GlyphError::NoSuchMotor {
+                    event,
+                    motor,
+                    motor_count,
+                })
+            }
+            EvType::Chord(motors)
+                if motors.is_empty() || motors.iter().any(|motor| motor >= motor_count) =>
+            {
+                return Err(GlyphError::BadChord { event })
+            }
+            EvType::EndGlyph if event + 1 < glyph.len() => {
+                return Err(GlyphError::EarlyEndGlyph { event })
+            }
+            _ => {}
+        }
+    }
+    if glyph.last().map(|ev| ev.ev_type) != Some(EvType::EndGlyph) {
+        return Err(GlyphError::MissingEndGlyph);
     }
     Ok(())
 }
+
+/// What a firmware build says it can do, see PKT_CAPABILITIES
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub protocol_version: u8,
+    pub motor_count: u8,
+    pub max_glyph_len: u16, // Longest PKT_GLYPH payload the firmware can buffer, in bytes
+    pub features: u8,       // FEATURE_* bits
+}
+
+impl Capabilities {
+    pub fn has(&self, feature: u8) -> bool {
+        self.features & feature == feature
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let [len_hi, len_lo] = self.max_glyph_len.to_be_bytes();
+        vec![
+            self.protocol_version,
+            self.motor_count,
+            len_hi,
+            len_lo,
+            self.features,
+        ]
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        match *payload {
+            [protocol_version, motor_count, len_hi, len_lo, features] => Some(Self {
+                protocol_version,
+                motor_count,
+                max_glyph_len: u16::from_be_bytes([len_hi, len_lo]),
+                features,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether the firmware accepts a glyph. Intensity and pulse length it doesn't support are
+    /// played at their defaults rather than rejected.
+    pub fn check_glyph(&self, glyph: &[Ev]) -> Result<(), GlyphError> {
+        check_glyph(glyph, self.motor_count)?;
+        if !self.has(FEATURE_CHORDS) {
+            if let Some(event) = glyph
+                .iter()
+                .position(|ev| matches!(ev.ev_type, EvType::Chord(_)))
+            {
+                return Err(GlyphError::ChordsUnsupported { event });
+            }
+        }
+        let len = glyph_payload(glyph).len();
+        if len > self.max_glyph_len as usize {
+            return Err(GlyphError::TooLong {
+                len,
+                max: self.max_glyph_len as usize,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let features: Vec<&str> = [
+            (FEATURE_CHORDS, "chords"),
+            (FEATURE_INTENSITY, "intensity"),
+            (FEATURE_PULSE_MS, "pulse length"),
+            (FEATURE_ECHO, "echo"),
+            (FEATURE_QUEUE, "queueing"),
+            (FEATURE_GLYPH_STORE, "glyph store"),
+            (FEATURE_ACK, "acknowledgements"),
+        ]
+        .into_iter()
+        .filter(|&(bit, _)| self.has(bit))
+        .map(|(_, name)| name)
+        .collect();
+        write!(
+            f,
+            "protocol version {}, {} motors, glyphs up to {} bytes, features: {}",
+            self.protocol_version,
+            self.motor_count,
+            self.max_glyph_len,
+            if features.is_empty() {
+                "none".to_owned()
+            } else {
+                features.join(", ")
+            }
+        )
+    }
+}
+
+/// Inverse of the PKT_GLYPH payload encoding
+pub fn decode_glyph_payload(payload: &[u8], motor_count: u8) -> Result<Vec<Ev>, GlyphError> {
+    let truncated = GlyphError::Truncated { len: payload.len() };
+    let mut glyph = vec![];
+    let mut rest = payload;
+    while !rest.is_empty() {
+        if rest.len() < EVENT_LEN {
+            return Err(truncated);
+        }
+        let (ch, tail) = rest.split_at(EVENT_LEN);
+        rest = tail;
+        let ev_type = match ch[0] {
+            EV_CHORD => {
+                let (&mask_len, tail) = rest.split_first().ok_or(truncated.clone())?;
+                let mask_len = mask_len as usize;
+                if mask_len > MAX_MASK_LEN {
+                    return Err(GlyphError::BadChord { event: glyph.len() });
+                }
+                if tail.len() < mask_len {
+                    return Err(truncated);
+                }
+                let (mask, tail) = tail.split_at(mask_len);
+                rest = tail;
+                EvType::Chord(MotorSet::from_mask(mask))
+            }
+            EV_END_GLYPH => EvType::EndGlyph,
+            motor => EvType::Motor(motor),
+        };
+        glyph.push(Ev {
+            ms_time: u16::from_be_bytes([ch[1], ch[2]]),
+            ev_type,
+            intensity: ch[3],
+            pulse_ms: u16::from_be_bytes([ch[4], ch[5]]),
+        });
+    }
+    check_glyph(&glyph, motor_count)?;
+    Ok(glyph)
+}
+
+/// What a stored glyph's times are multiplied by when it plays, `num / den` rounded down
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeScale {
+    pub num: u16,
+    pub den: u16,
+}
+
+impl TimeScale {
+    /// None if the time no longer fits in a u16, or `den` is 0
+    pub fn apply(&self, ms: u16) -> Option<u16> {
+        let scaled = (ms as u32 * self.num as u32).checked_div(self.den as u32)?;
+        u16::try_from(scaled).ok()
+    }
+
+    /// A stored glyph the way it plays
+    pub fn scale_glyph(&self, glyph: &[Ev]) -> Option<Vec<Ev>> {
+        glyph
+            .iter()
+            .map(|ev| {
+                let ms_time = self.apply(ev.ms_time)?;
+                Some(Ev { ms_time, ..*ev })
+            })
+            .collect()
+    }
+}
+
+/// What the firmware says it has stored, see PKT_STORE_SUMMARY
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StoreSummary {
+    pub count: u16,
+    pub checksum: u16,
+}
+
+impl std::fmt::Display for StoreSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} glyphs, checksum {:04x}", self.count, self.checksum)
+    }
+}
+
+pub fn encode_store_glyph(id: u16, events: &[Ev]) -> Vec<u8> {
+    let payload = [id.to_be_bytes().as_slice(), &glyph_payload(events)].concat();
+    encode_packet(PKT_STORE_GLYPH, &payload)
+}
+
+/// The id and PKT_GLYPH payload of a PKT_STORE_GLYPH payload, None if it's too short for an id
+pub fn split_store_payload(payload: &[u8]) -> Option<(u16, &[u8])> {
+    let (id, glyph) = payload.split_at_checked(2)?;
+    Some((u16::from_be_bytes([id[0], id[1]]), glyph))
+}
+
+pub fn encode_play_stored(id: u16, scale: TimeScale) -> Vec<u8> {
+    let payload = [
+        id.to_be_bytes(),
+        scale.num.to_be_bytes(),
+        scale.den.to_be_bytes(),
+    ]
+    .concat();
+    encode_packet(PKT_PLAY_STORED, &payload)
+}
+
+/// Inverse of encode_play_stored's payload
+pub fn decode_play_stored_payload(payload: &[u8]) -> Option<(u16, TimeScale)> {
+    let [id_hi, id_lo, num_hi, num_lo, den_hi, den_lo] = *payload else {
+        return None;
+    };
+    let scale = TimeScale {
+        num: u16::from_be_bytes([num_hi, num_lo]),
+        den: u16::from_be_bytes([den_hi, den_lo]),
+    };
+    Some((u16::from_be_bytes([id_hi, id_lo]), scale))
+}
+
+/// CRC-16 over (id_hi, id_lo, len_hi, len_lo, payload...) for every stored glyph in id order, where
+/// payload is the glyph's PKT_GLYPH payload and len its length
+pub fn store_checksum(glyphs: &BTreeMap<u16, Vec<Ev>>) -> u16 {
+    let mut bytes = vec![];
+    for (id, glyph) in glyphs {
+        let payload = glyph_payload(glyph);
+        bytes.extend(id.to_be_bytes());
+        bytes.extend((payload.len() as u16).to_be_bytes());
+        bytes.extend(payload);
+    }
+    crc16(&bytes)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    Packet(PacketError),
+    Glyph(GlyphError),
+    NotFromHost { kind: u8 }, // A kind only the firmware sends, or no kind at all
+    BadPayload { kind: u8 },  // Too short or too long for its kind
+    Unterminated,             // The stream ended part way through a packet
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Packet(e) => write!(f, "{}", e),
+            DecodeError::Glyph(e) => write!(f, "{}", e),
+            DecodeError::NotFromHost { kind } => {
+                write!(f, "packet kind {:#04x} is not one the host sends", kind)
+            }
+            DecodeError::BadPayload { kind } => {
+                write!(
+                    f,
+                    "packet kind {:#04x} has a payload of the wrong length",
+                    kind
+                )
+            }
+            DecodeError::Unterminated => write!(f, "truncated frame, stream ended mid packet"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A packet the host sends, as decoded from a capture
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Glyph(Vec<Ev>),
+    QueueGlyph(Vec<Ev>),
+    StoreGlyph { id: u16, glyph: Vec<Ev> },
+    PlayStored { id: u16, scale: TimeScale },
+    Stop,
+    ClearStore,
+    Query { kind: u8 }, // PKT_IDENTIFY, PKT_QUERY_CAPS, PKT_QUERY_STORE or PKT_ECHO, nothing plays
+}
+
+/// A packet, or the reason it couldn't be decoded, along with the byte offset it started at
+pub type DecodedFrame = (usize, Result<Frame, DecodeError>);
+
+/// Inverse of encode_glyph and the other host packets over a whole captured stream
+pub fn decode_glyphs(bytes: &[u8], motor_count: u8) -> Vec<DecodedFrame> {
+    let mut decoder = PacketDecoder::default();
+    let mut frames = vec![];
+    let mut start = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if byte == SLIP_END && !decoder.in_packet() {
+            start = i;
+        }
+        let res = match decoder.push(byte) {
+            None => continue,
+            Some(Ok(packet)) => decode_frame(&packet, motor_count),
+            Some(Err(e)) => Err(DecodeError::Packet(e)),
+        };
+        frames.push((start, res));
+        start = i;
+    }
+    if decoder.in_packet() {
+        frames.push((start, Err(DecodeError::Unterminated)));
+    }
+    frames
+}
+
+fn decode_frame(packet: &Packet, motor_count: u8) -> Result<Frame, DecodeError> {
+    let (kind, payload) = (packet.kind, packet.payload.as_slice());
+    let glyph = |payload| decode_glyph_payload(payload, motor_count).map_err(DecodeError::Glyph);
+    let no_payload = |frame| match payload {
+        [] => Ok(frame),
+        _ => Err(DecodeError::BadPayload { kind }),
+    };
+    match kind {
+        PKT_GLYPH => glyph(payload).map(Frame::Glyph),
+        PKT_QUEUE_GLYPH => glyph(payload).map(Frame::QueueGlyph),
+        PKT_STORE_GLYPH => {
+            let (id, payload) =
+                split_store_payload(payload).ok_or(DecodeError::BadPayload { kind })?;
+            Ok(Frame::StoreGlyph {
+                id,
+                glyph: glyph(payload)?,
+            })
+        }
+        PKT_PLAY_STORED => decode_play_stored_payload(payload)
+            .map(|(id, scale)| Frame::PlayStored { id, scale })
+            .ok_or(DecodeError::BadPayload { kind }),
+        PKT_STOP => no_payload(Frame::Stop),
+        PKT_CLEAR_STORE => no_payload(Frame::ClearStore),
+        PKT_IDENTIFY | PKT_QUERY_CAPS | PKT_QUERY_STORE | PKT_ECHO => Ok(Frame::Query { kind }),
+        _ => Err(DecodeError::NotFromHost { kind }),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceMsg {
+    Ack,
+    Started,
+    Finished,
+    Error(u8),
+    Identity(String),
+    Capabilities(Capabilities),
+    Echo(Vec<u8>),
+    StoreSummary(StoreSummary),
+}
+
+impl DeviceMsg {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            DeviceMsg::Ack => encode_packet(PKT_ACK, &[]),
+            DeviceMsg::Started => encode_packet(PKT_STARTED, &[]),
+            DeviceMsg::Finished => encode_packet(PKT_FINISHED, &[]),
+            DeviceMsg::Error(code) => encode_packet(PKT_ERROR, &[*code]),
+            DeviceMsg::Identity(id) => encode_packet(PKT_IDENTITY, id.as_bytes()),
+            DeviceMsg::Capabilities(caps) => encode_packet(PKT_CAPABILITIES, &caps.encode()),
+            DeviceMsg::Echo(payload) => encode_packet(PKT_ECHO_REPLY, payload),
+            DeviceMsg::StoreSummary(summary) => {
+                let payload = [summary.count.to_be_bytes(), summary.checksum.to_be_bytes()];
+                encode_packet(PKT_STORE_SUMMARY, &payload.concat())
+            }
+        }
+    }
+
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        match (packet.kind, packet.payload.as_slice()) {
+            (PKT_ACK, []) => Some(DeviceMsg::Ack),
+            (PKT_STARTED, []) => Some(DeviceMsg::Started),
+            (PKT_FINISHED, []) => Some(DeviceMsg::Finished),
+            (PKT_ERROR, [code]) => Some(DeviceMsg::Error(*code)),
+            (PKT_IDENTITY, id) => Some(DeviceMsg::Identity(String::from_utf8_lossy(id).into())),
+            (PKT_CAPABILITIES, caps) => Capabilities::decode(caps).map(DeviceMsg::Capabilities),
+            (PKT_ECHO_REPLY, payload) => Some(DeviceMsg::Echo(payload.to_vec())),
+            (PKT_STORE_SUMMARY, &[count_hi, count_lo, sum_hi, sum_lo]) => {
+                Some(DeviceMsg::StoreSummary(StoreSummary {
+                    count: u16::from_be_bytes([count_hi, count_lo]),
+                    checksum: u16::from_be_bytes([sum_hi, sum_lo]),
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn describe_device_error(code: u8) -> &'static str {
+    match code {
+        ERR_MALFORMED_FRAME => "malformed frame",
+        ERR_BAD_EVENT_TYPE => "unknown event type",
+        ERR_BAD_CRC => "checksum mismatch",
+        ERR_BAD_VERSION => "unsupported protocol version",
+        ERR_UNKNOWN_COMMAND => "unknown command",
+        ERR_MISSING_END_GLYPH => "missing EndGlyph",
+        ERR_GLYPH_TOO_LONG => "glyph too long",
+        ERR_STORE_FULL => "no room left to store the glyph",
+        ERR_NO_STORED_GLYPH => "no glyph stored under that id",
+        _ => "unknown error",
+    }
+}
+
+/// Turns the byte stream coming back from the firmware into messages, corrupt or unrecognised
+/// packets are skipped
+#[derive(Default)]
+pub struct DeviceMsgDecoder {
+    packets: PacketDecoder,
+}
+
+impl DeviceMsgDecoder {
+    pub fn push(&mut self, byte: u8) -> Option<DeviceMsg> {
+        match self.packets.push(byte)? {
+            Ok(packet) => DeviceMsg::from_packet(&packet),
+            Err(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(bytes: &[u8]) -> Vec<Result<Packet, PacketError>> {
+        let mut decoder = PacketDecoder::default();
+        bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let payloads: [&[u8]; 4] = [
+            &[],
+            &[1, 2, 3],
+            &[SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_ESC_ESC, 0xF5],
+            &[SLIP_END; 16],
+        ];
+        for payload in payloads {
+            let encoded = encode_packet(PKT_GLYPH, payload);
+            // END only appears as the delimiters
+            assert_eq!(encoded.iter().filter(|&&b| b == SLIP_END).count(), 2);
+            let decoded = decode_all(&encoded);
+            assert_eq!(
+                decoded,
+                vec![Ok(Packet {
+                    kind: PKT_GLYPH,
+                    payload: payload.to_vec()
+                })]
+            );
+        }
+    }
+
+    #[test]
+    fn glyph_with_colliding_times_round_trips() {
+        // 192 ms is 0x00C0 and 0xF5C0 is both old delimiters
+        let glyph = [
+            Ev::motor(0, 3),
+            Ev::motor(192, 4).with_intensity(SLIP_END),
+            Ev::motor(0xF5C0, 5).with_intensity(0).with_pulse_ms(0xDBC0),
+            Ev::end(0xFFDB),
+        ];
+        let decoded = decode_all(&encode_glyph(&glyph));
+        let payload = &decoded[0].as_ref().unwrap().payload;
+        assert_eq!(decode_glyph_payload(payload, 12), Ok(glyph.to_vec()));
+    }
+
+    #[test]
+    fn chords_round_trip() {
+        let glyph = [
+            Ev::chord(0, &[0, 1, 2, 3]),
+            Ev::motor(30, 6),
+            Ev::chord(60, &[3, 9, 20]).with_intensity(100),
+            Ev::end(90),
+        ];
+        let decoded = decode_glyphs(&encode_glyph(&glyph), 21);
+        assert_eq!(decoded, vec![(0, Ok(Frame::Glyph(glyph.to_vec())))]);
+
+        // Motor 20 doesn't exist on a 12 motor board
+        let decoded = decode_glyphs(&encode_glyph(&glyph), 12);
+        let bad_chord = DecodeError::Glyph(GlyphError::BadChord { event: 2 });
+        assert_eq!(decoded, vec![(0, Err(bad_chord))]);
+
+        let empty = [Ev::chord(0, &[]), Ev::end(30)];
+        assert_eq!(
+            check_glyph(&empty, 12),
+            Err(GlyphError::BadChord { event: 0 })
+        );
+    }
+
+    #[test]
+    fn glyphs_are_checked_in_order() {
+        let glyph = [Ev::motor(0, 3), Ev::motor(50, 11), Ev::end(100)];
+        assert_eq!(check_glyph(&glyph, 12), Ok(()));
+        assert_eq!(
+            check_glyph(&glyph, 11),
+            Err(GlyphError::NoSuchMotor {
+                event: 1,
+                motor: 11,
+                motor_count: 11
+            })
+        );
+
+        let backwards = [Ev::motor(50, 3), Ev::motor(0, 4), Ev::end(100)];
+        assert_eq!(
+            check_glyph(&backwards, 12),
+            Err(GlyphError::TimeGoesBack {
+                event: 1,
+                ms_time: 0,
+                previous_ms: 50
+            })
+        );
+
+        let two_ends = [Ev::motor(0, 3), Ev::end(50), Ev::end(100)];
+        assert_eq!(
+            check_glyph(&two_ends, 12),
+            Err(GlyphError::EarlyEndGlyph { event: 1 })
+        );
+        assert_eq!(check_glyph(&[], 12), Err(GlyphError::MissingEndGlyph));
+    }
+
+    #[test]
+    fn consecutive_packets_are_split() {
+        let mut bytes = encode_packet(PKT_ACK, &[]);
+        bytes.extend(encode_packet(PKT_ERROR, &[ERR_BAD_CRC]));
+        bytes.extend(encode_packet(PKT_FINISHED, &[]));
+        let mut decoder = DeviceMsgDecoder::default();
+        let msgs: Vec<DeviceMsg> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                DeviceMsg::Ack,
+                DeviceMsg::Error(ERR_BAD_CRC),
+                DeviceMsg::Finished
+            ]
+        );
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let mut encoded = encode_packet(PKT_GLYPH, &[0, 0, 30, 12, 0, 60]);
+        encoded[4] ^= 0x01;
+        assert_eq!(decode_all(&encoded), vec![Err(PacketError::BadCrc)]);
+
+        let encoded = encode_packet(PKT_GLYPH, &[1]);
+        let truncated = [&encoded[..3], &[SLIP_END]].concat();
+        assert_eq!(decode_all(&truncated), vec![Err(PacketError::TooShort)]);
+
+        let bad_escape = [SLIP_END, SLIP_ESC, 0x00, 1, 2, 3, SLIP_END];
+        assert_eq!(decode_all(&bad_escape), vec![Err(PacketError::BadEscape)]);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut body = vec![PROTOCOL_VERSION + 1, PKT_ACK];
+        body.extend(crc16(&body).to_be_bytes());
+        let bytes = [&[SLIP_END], body.as_slice(), &[SLIP_END]].concat();
+        assert_eq!(
+            decode_all(&bytes),
+            vec![Err(PacketError::BadVersion(PROTOCOL_VERSION + 1))]
+        );
+    }
+
+    #[test]
+    fn capabilities_are_read_from_any_version() {
+        let caps = Capabilities {
+            protocol_version: PROTOCOL_VERSION + 1,
+            motor_count: 16,
+            max_glyph_len: 300,
+            features: FEATURE_CHORDS | FEATURE_PULSE_MS,
+        };
+        let mut body = vec![PROTOCOL_VERSION + 1, PKT_CAPABILITIES];
+        body.extend(caps.encode());
+        body.extend(crc16(&body).to_be_bytes());
+        let bytes = [&[SLIP_END], body.as_slice(), &[SLIP_END]].concat();
+        let mut decoder = DeviceMsgDecoder::default();
+        let msgs: Vec<_> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert_eq!(msgs, vec![DeviceMsg::Capabilities(caps)]);
+    }
+
+    #[test]
+    fn capabilities_limit_glyphs() {
+        let glyph = [Ev::motor(0, 3), Ev::chord(30, &[0, 1]), Ev::end(60)];
+        let mut caps = Capabilities {
+            protocol_version: PROTOCOL_VERSION,
+            motor_count: 12,
+            max_glyph_len: 20,
+            features: FEATURE_CHORDS,
+        };
+        // 3 events and a one byte chord mask
+        assert_eq!(caps.check_glyph(&glyph), Ok(()));
+        caps.max_glyph_len = 19;
+        assert_eq!(
+            caps.check_glyph(&glyph),
+            Err(GlyphError::TooLong { len: 20, max: 19 })
+        );
+        caps.max_glyph_len = 20;
+        caps.features = 0;
+        assert_eq!(
+            caps.check_glyph(&glyph),
+            Err(GlyphError::ChordsUnsupported { event: 1 })
+        );
+    }
+
+    #[test]
+    fn time_scale_rounds_down() {
+        let glyph = [Ev::motor(0, 1), Ev::motor(1, 2), Ev::end(2)];
+        let scale = TimeScale { num: 50, den: 3 };
+        let scaled = scale.scale_glyph(&glyph).unwrap();
+        let times: Vec<u16> = scaled.iter().map(|ev| ev.ms_time).collect();
+        assert_eq!(times, vec![0, 16, 33]);
+
+        let too_slow = TimeScale { num: 40000, den: 1 };
+        assert_eq!(too_slow.scale_glyph(&glyph), None);
+        assert_eq!(TimeScale { num: 1, den: 0 }.apply(10), None);
+    }
+
+    #[test]
+    fn decode_glyphs_reports_errors_with_offsets() {
+        let good = [Ev::motor(0, 8), Ev::motor(192, 4), Ev::end(300)];
+        let mut bytes = encode_glyph(&good);
+        let second = bytes.len();
+        bytes.extend(encode_packet(
+            PKT_GLYPH,
+            &[3, 0, 0, 255, 0, 0, 200, 0, 5, 255, 0, 0],
+        ));
+        bytes.extend(encode_packet(PKT_GLYPH, &[3, 0, 0, 255, 0, 0]));
+        bytes.extend(encode_packet(PKT_GLYPH, &[3, 0, 0, 255]));
+        bytes.extend(encode_packet(PKT_ACK, &[]));
+        bytes.extend(&encode_glyph(&good)[..5]);
+
+        let frames = decode_glyphs(&bytes, 12);
+        assert_eq!(frames[0], (0, Ok(Frame::Glyph(good.to_vec()))));
+        assert_eq!(frames[1].0, second);
+        let errors: Vec<DecodeError> = frames[1..]
+            .iter()
+            .map(|(_, f)| f.clone().unwrap_err())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                DecodeError::Glyph(GlyphError::NoSuchMotor {
+                    event: 1,
+                    motor: 200,
+                    motor_count: 12
+                }),
+                DecodeError::Glyph(GlyphError::MissingEndGlyph),
+                DecodeError::Glyph(GlyphError::Truncated { len: 4 }),
+                DecodeError::NotFromHost { kind: PKT_ACK },
+                DecodeError::Unterminated,
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_glyphs_reads_every_host_packet() {
+        let glyph = [Ev::motor(0, 8), Ev::chord(30, &[4, 5]), Ev::end(60)];
+        let scale = TimeScale { num: 3, den: 2 };
+        let mut bytes = encode_packet(PKT_QUERY_CAPS, &[]);
+        bytes.extend(encode_packet(PKT_CLEAR_STORE, &[]));
+        bytes.extend(encode_store_glyph(7, &glyph));
+        bytes.extend(encode_play_stored(7, scale));
+        bytes.extend(encode_glyph(&glyph));
+        bytes.extend(encode_queued_glyph(&glyph));
+        bytes.extend(encode_packet(PKT_STOP, &[]));
+        bytes.extend(encode_packet(PKT_ECHO, &[1, 2, 3]));
+        bytes.extend(encode_packet(PKT_STOP, &[1]));
+        bytes.extend(encode_packet(PKT_PLAY_STORED, &[0, 7]));
+        bytes.extend(encode_packet(PKT_STORE_GLYPH, &[0]));
+
+        let frames: Vec<Result<Frame, DecodeError>> = decode_glyphs(&bytes, 12)
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                Ok(Frame::Query {
+                    kind: PKT_QUERY_CAPS
+                }),
+                Ok(Frame::ClearStore),
+                Ok(Frame::StoreGlyph {
+                    id: 7,
+                    glyph: glyph.to_vec()
+                }),
+                Ok(Frame::PlayStored { id: 7, scale }),
+                Ok(Frame::Glyph(glyph.to_vec())),
+                Ok(Frame::QueueGlyph(glyph.to_vec())),
+                Ok(Frame::Stop),
+                Ok(Frame::Query { kind: PKT_ECHO }),
+                Err(DecodeError::BadPayload { kind: PKT_STOP }),
+                Err(DecodeError::BadPayload {
+                    kind: PKT_PLAY_STORED
+                }),
+                Err(DecodeError::BadPayload {
+                    kind: PKT_STORE_GLYPH
+                }),
+            ]
+        );
+    }
+}
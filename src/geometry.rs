@@ -0,0 +1,286 @@
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use crate::profile::{Motor, Profile};
+
+/*
+Glyphs can be drawn as paths instead of motor indices, so that one alphabet plays on any board:
+  path = [[0, 2], [0, 0], [3, 0]]   up the first column, then along the fingers
+Points are on the alphabet's canvas, which has the axes of a profile's motors: x from the first
+column to the last, y from the fingers towards the wrist. The canvas is stretched over the board
+that plays the alphabet, its edges meet the outermost motors, and the path is traced over whichever
+motors it passes.
+*/
+
+/// The area an alphabet's paths are drawn on, see above
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Canvas {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+/// A point on a canvas, x then y
+pub type Point = [f32; 2];
+
+impl Canvas {
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.width > 0.0 && self.height > 0.0 {
+            Ok(())
+        } else {
+            Err(anyhow!("the canvas needs a width and a height above 0"))
+        }
+    }
+
+    fn contains(&self, [x, y]: Point) -> bool {
+        (0.0..=self.width).contains(&x) && (0.0..=self.height).contains(&y)
+    }
+}
+
+/// The motors a path drawn on `canvas` passes on the board, in the order it reaches them.
+///
+/// A motor is passed when the path comes within half the way to the motor's nearest neighbour, and
+/// where the path comes closest to it no other motor is nearer. A path running halfway between two
+/// rows so passes the first of them rather than zigzagging across both. A motor passed twice in a
+/// row plays once.
+pub fn trace_path(path: &[Point], canvas: Canvas, profile: &Profile) -> anyhow::Result<Vec<u8>> {
+    if let Some(point) = path.iter().find(|&&point| !canvas.contains(point)) {
+        return Err(anyhow!(
+            "{:?} is off the canvas, which goes to [{}, {}]",
+            point,
+            canvas.width,
+            canvas.height
+        ));
+    }
+    let motors = &profile.motors;
+    let stretch = Stretch::new(canvas, motors);
+    let points: Vec<(f32, f32)> = path.iter().map(|&point| stretch.apply(point)).collect();
+    let segments: Vec<((f32, f32), (f32, f32))> = match points.as_slice() {
+        [] => return Err(anyhow!("the path has no points")),
+        [point] => vec![(*point, *point)],
+        _ => points.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+    };
+    let reach: Vec<f32> = (0..motors.len()).map(|i| reach(motors, i)).collect();
+
+    let mut passed = vec![];
+    for (start, end) in segments {
+        let mut on_segment: Vec<(f32, u8)> = vec![];
+        for (i, motor) in motors.iter().enumerate() {
+            let (t, closest) = closest_point(start, end, (motor.x, motor.y));
+            if distance(closest, (motor.x, motor.y)) <= reach[i] && nearest(motors, closest) == i {
+                on_segment.push((t, i as u8));
+            }
+        }
+        on_segment.sort_by(|a, b| a.0.total_cmp(&b.0));
+        passed.extend(on_segment.into_iter().map(|(_, motor)| motor));
+    }
+    passed.dedup();
+    if passed.is_empty() {
+        return Err(anyhow!(
+            "the path passes no motor of the {} board",
+            profile.name
+        ));
+    }
+    Ok(passed)
+}
+
+/// How a canvas is scaled and moved onto a board
+struct Stretch {
+    origin: (f32, f32),
+    scale: (f32, f32),
+}
+
+impl Stretch {
+    fn new(canvas: Canvas, motors: &[Motor]) -> Self {
+        let min_x = motors.iter().map(|m| m.x).fold(f32::INFINITY, f32::min);
+        let max_x = motors.iter().map(|m| m.x).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = motors.iter().map(|m| m.y).fold(f32::INFINITY, f32::min);
+        let max_y = motors.iter().map(|m| m.y).fold(f32::NEG_INFINITY, f32::max);
+        Self {
+            origin: (min_x, min_y),
+            scale: (
+                (max_x - min_x) / canvas.width,
+                (max_y - min_y) / canvas.height,
+            ),
+        }
+    }
+
+    fn apply(&self, [x, y]: Point) -> (f32, f32) {
+        (
+            self.origin.0 + x * self.scale.0,
+            self.origin.1 + y * self.scale.1,
+        )
+    }
+}
+
+/// Half the distance from a motor to its nearest neighbour, a board's only motor reaches anywhere
+fn reach(motors: &[Motor], i: usize) -> f32 {
+    let at = (motors[i].x, motors[i].y);
+    let others = motors.iter().enumerate().filter(|&(j, _)| j != i);
+    let nearest = others
+        .map(|(_, m)| distance(at, (m.x, m.y)))
+        .fold(f32::INFINITY, f32::min);
+    nearest / 2.0
+}
+
+/// Index of the motor nearest to a point, the lowest of those equally near
+fn nearest(motors: &[Motor], point: (f32, f32)) -> usize {
+    let distances = motors.iter().map(|m| distance(point, (m.x, m.y)));
+    distances
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+/// How far along the segment from `start` to `end` it comes closest to `point`, from 0 to 1, and
+/// where
+fn closest_point(start: (f32, f32), end: (f32, f32), point: (f32, f32)) -> (f32, (f32, f32)) {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let len_sq = dx * dx + dy * dy;
+    if len_sq == 0.0 {
+        return (0.0, start);
+    }
+    let t = ((point.0 - start.0) * dx + (point.1 - start.1) * dy) / len_sq;
+    let t = t.clamp(0.0, 1.0);
+    (t, (start.0 + t * dx, start.1 + t * dy))
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(name: &str, coords: &[(f32, f32)]) -> Profile {
+        Profile {
+            name: name.to_owned(),
+            motors: coords.iter().map(|&(x, y)| Motor { x, y }).collect(),
+            ..Profile::prototype()
+        }
+    }
+
+    /// A board of `columns` by `rows` motors a unit apart, numbered row by row from the fingers
+    fn grid(columns: u8, rows: u8) -> Profile {
+        let coords: Vec<(f32, f32)> = (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x as f32, y as f32)))
+            .collect();
+        board(&format!("{}x{}", columns, rows), &coords)
+    }
+
+    /// Eight motors around the edge of a 4x4 square and one in the middle
+    fn ring() -> Profile {
+        let coords = [
+            (1.0, 0.0),
+            (2.0, 0.0),
+            (3.0, 1.0),
+            (3.0, 2.0),
+            (2.0, 3.0),
+            (1.0, 3.0),
+            (0.0, 2.0),
+            (0.0, 1.0),
+            (1.5, 1.5),
+        ];
+        board("ring", &coords)
+    }
+
+    const WIDE: Canvas = Canvas {
+        width: 3.0,
+        height: 2.0,
+    };
+
+    #[test]
+    fn paths_stretch_over_any_grid() {
+        // Up the first column, then along the fingers
+        let path = [[0.0, 2.0], [0.0, 0.0], [3.0, 0.0]];
+        let trace = |profile: &Profile| trace_path(&path, WIDE, profile).unwrap();
+        assert_eq!(trace(&Profile::prototype()), [8, 4, 0, 1, 2, 3]);
+        assert_eq!(trace(&grid(3, 2)), [3, 0, 1, 2]);
+        assert_eq!(trace(&grid(4, 4)), [12, 8, 4, 0, 1, 2, 3]);
+        assert_eq!(trace(&grid(1, 1)), [0]);
+    }
+
+    #[test]
+    fn paths_pass_the_motors_they_come_near() {
+        let profile = Profile::prototype();
+        let trace = |path: &[Point]| trace_path(path, WIDE, &profile).unwrap();
+        // Halfway between two rows passes the first of them
+        assert_eq!(trace(&[[0.0, 0.5], [3.0, 0.5]]), [0, 1, 2, 3]);
+        // A diagonal passes the motors it runs through, not the ones beside it
+        assert_eq!(trace(&[[0.0, 0.0], [2.0, 2.0]]), [0, 5, 10]);
+        assert_eq!(trace(&[[3.0, 2.0]]), [11]);
+        // Going back over a motor plays it again, staying on one doesn't
+        assert_eq!(trace(&[[0.0, 0.0], [1.0, 0.0], [0.0, 0.0]]), [0, 1, 0]);
+        assert_eq!(trace(&[[0.0, 0.0], [0.1, 0.0], [0.2, 0.0]]), [0]);
+    }
+
+    #[test]
+    fn paths_trace_boards_that_are_not_grids() {
+        let profile = ring();
+        let square = Canvas {
+            width: 3.0,
+            height: 3.0,
+        };
+        let around = [
+            [1.0, 0.0],
+            [2.0, 0.0],
+            [3.0, 1.0],
+            [3.0, 2.0],
+            [2.0, 3.0],
+            [1.0, 3.0],
+            [0.0, 2.0],
+            [0.0, 1.0],
+            [1.0, 0.0],
+        ];
+        let trace = |path: &[Point]| trace_path(path, square, &profile).unwrap();
+        assert_eq!(trace(&around), [0, 1, 2, 3, 4, 5, 6, 7, 0]);
+        assert_eq!(trace(&[[0.0, 1.5], [3.0, 1.5]]), [6, 8, 2]);
+    }
+
+    #[test]
+    fn bad_paths_are_errors() {
+        let error = |path: &[Point], profile: &Profile| {
+            trace_path(path, WIDE, profile).unwrap_err().to_string()
+        };
+        let prototype = Profile::prototype();
+        assert_eq!(
+            error(&[[0.0, 0.0], [3.5, 0.0]], &prototype),
+            "[3.5, 0.0] is off the canvas, which goes to [3, 2]"
+        );
+        assert_eq!(error(&[], &prototype), "the path has no points");
+        // The middle of a square of motors is further from each than their reach
+        assert_eq!(
+            error(&[[1.5, 1.0]], &grid(2, 2)),
+            "the path passes no motor of the 2x2 board"
+        );
+    }
+
+    #[test]
+    fn reach_is_half_the_way_to_the_nearest_motor() {
+        let prototype = Profile::prototype();
+        assert!((0..12).all(|i| reach(&prototype.motors, i) == 0.5));
+        let ring = ring();
+        assert_eq!(reach(&ring.motors, 0), 0.5);
+        assert!((reach(&ring.motors, 8) - 2.5f32.sqrt() / 2.0).abs() < 1e-6);
+        assert_eq!(reach(&grid(1, 1).motors, 0), f32::INFINITY);
+    }
+
+    #[test]
+    fn nearest_takes_the_lowest_of_equally_near_motors() {
+        let motors = Profile::prototype().motors;
+        assert_eq!(nearest(&motors, (1.4, 0.6)), 5);
+        assert_eq!(nearest(&motors, (0.5, 0.0)), 0);
+        assert_eq!(nearest(&motors, (1.5, 1.5)), 5);
+        assert_eq!(nearest(&motors, (9.0, 9.0)), 11);
+        assert_eq!(nearest(&ring().motors, (1.4, 1.6)), 8);
+    }
+}
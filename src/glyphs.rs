@@ -1,11 +1,35 @@
-use std::{collections::HashMap, iter};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs, iter,
+    path::Path,
+};
 
+use anyhow::anyhow;
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
 use tabled::settings::{width, Style};
 
-use crate::event::{Ev, EvType::EndGlyph};
+use crate::{
+    device::TactileDevice,
+    event::{
+        glyph_payload, store_checksum, Ev, EvType, GlyphError, StoreSummary, DEFAULT_PULSE_MS,
+        EV_CHORD, FULL_INTENSITY, PROTOCOL_VERSION,
+    },
+    geometry::{trace_path, Canvas, Point},
+    notation::{format_glyph, glyph_names, parse_glyph},
+    profile::Profile,
+    stream::Timeline,
+};
+
+/// Alphabets that come with the program, by name
+const BUILT_IN: [(&str, &str); 2] = [
+    ("distinguish", include_str!("../alphabets/distinguish.toml")),
+    ("roud_graff", include_str!("../alphabets/roud_graff.toml")),
+];
 
 pub struct Alphabet {
+    pub name: String,
     ascii_block: Vec<Vec<Ev>>, // from ' ' to '~' inclusive
     char_map: HashMap<char, Vec<Ev>>,
     pub other_map: HashMap<String, Vec<Ev>>,
@@ -14,10 +38,11 @@ pub struct Alphabet {
 
 impl Default for Alphabet {
     fn default() -> Self {
-        let default_glyph = vec![Ev::new(0, 0), Ev::new(200, EndGlyph as u8)];
+        let default_glyph = vec![Ev::motor(0, 0), Ev::end(200)];
         let mut char_map = HashMap::new();
         char_map.insert('\n', default_glyph.clone());
         Self {
+            name: String::new(),
             ascii_block: vec![default_glyph.clone(); 95],
             char_map,
             other_map: HashMap::new(),
@@ -27,14 +52,117 @@ impl Default for Alphabet {
 }
 
 impl Alphabet {
-    fn add_other_glyphs(&mut self, gls: Vec<(&str, Vec<Ev>)>) {
-        for (s, evs) in gls.into_iter() {
-            self.add_other_glyph(s, evs);
+    /// A built-in alphabet by name, or else an alphabet file, see alphabets/distinguish.toml for
+    /// how they are written. Glyphs drawn as paths are traced over the board's motors.
+    pub fn find(name_or_path: &str, profile: &Profile) -> anyhow::Result<Self> {
+        match BUILT_IN.iter().find(|&&(name, _)| name == name_or_path) {
+            Some((name, text)) => Self::parse(text, profile).map_err(|e| {
+                anyhow!(
+                    "The built-in {} alphabet doesn't fit the {} board: {}",
+                    name,
+                    profile.name,
+                    e
+                )
+            }),
+            None if !Path::new(name_or_path).exists() => Err(anyhow!(
+                "There is no alphabet file {} and no built-in alphabet of that name, the built-in \
+                 ones are {}",
+                name_or_path,
+                BUILT_IN.map(|(name, _)| name).join(", ")
+            )),
+            None => Self::load(Path::new(name_or_path), profile),
+        }
+    }
+
+    pub fn load(path: &Path, profile: &Profile) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Can't read alphabet {}: {}", path.display(), e))?;
+        Self::parse(&text, profile).map_err(|e| anyhow!("Bad alphabet {}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str, profile: &Profile) -> anyhow::Result<Self> {
+        let file: AlphabetFile = toml::from_str(text)?;
+        let canvas = file.canvas.unwrap_or_default();
+        canvas.check()?;
+        let mut a_bet = Alphabet {
+            name: file.name,
+            ..Alphabet::default()
+        };
+        if let Some(space_ms) = file.each_motor_ms {
+            for motor in 0..profile.motor_count() {
+                let glyph = equal_spaced_evs(&[motor], space_ms);
+                a_bet.other_map.insert(motor.to_string(), glyph);
+            }
+        }
+        // Glyphs can be made of other named glyphs, those have to be built first
+        let mut pending: Vec<(&String, &GlyphSpec)> = file.named.iter().collect();
+        while !pending.is_empty() {
+            let (ready, waiting): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(_, spec)| {
+                spec.parts()
+                    .iter()
+                    .all(|part| a_bet.other_map.contains_key(*part))
+            });
+            if ready.is_empty() {
+                let missing = waiting.iter().find_map(|&(name, spec)| {
+                    let part = spec
+                        .parts()
+                        .into_iter()
+                        .find(|part| !file.named.contains_key(*part));
+                    part.map(|part| (name, part))
+                });
+                return Err(match missing {
+                    Some((name, part)) => {
+                        anyhow!("glyph {}: there is no glyph called {}", name, part)
+                    }
+                    None => {
+                        let names: Vec<&str> =
+                            waiting.iter().map(|(name, _)| name.as_str()).collect();
+                        anyhow!("glyphs {} are made of each other", names.join(", "))
+                    }
+                });
+            }
+            for (name, spec) in ready {
+                let glyph = spec
+                    .build(&a_bet.other_map, canvas, profile)
+                    .map_err(|e| anyhow!("glyph {}: {}", name, e))?;
+                a_bet.other_map.insert(name.clone(), glyph);
+            }
+            pending = waiting;
+        }
+        if let Some(def) = &file.unknown {
+            let glyph = def
+                .build(&a_bet.other_map, canvas, profile)
+                .map_err(|e| anyhow!("the unknown glyph: {}", e))?;
+            a_bet.ascii_block = vec![glyph.clone(); a_bet.ascii_block.len()];
+            a_bet.char_map.insert('\n', glyph.clone());
+            a_bet.unknown_glyph = glyph;
         }
+        for (key, def) in &file.chars {
+            let mut chars = key.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(anyhow!("{:?} under [chars] isn't a single character", key));
+            };
+            let glyph = def
+                .build(&a_bet.other_map, canvas, profile)
+                .map_err(|e| anyhow!("glyph {:?}: {}", c, e))?;
+            if (' '..='~').contains(&c) {
+                a_bet.ascii_block[c as usize - ' ' as usize] = glyph;
+            } else {
+                a_bet.char_map.insert(c, glyph);
+            }
+        }
+        Ok(a_bet)
     }
 
-    fn add_other_glyph(&mut self, s: &str, g: Vec<Ev>) {
-        self.other_map.insert(s.to_owned(), g);
+    /// A glyph written in the notation of `notation::parse_glyph`, it can be stitched from this
+    /// alphabet's named glyphs
+    pub fn parse_glyph(&self, text: &str, motor_count: u8) -> anyhow::Result<Vec<Ev>> {
+        parse_glyph(text, motor_count, &self.other_map)
+    }
+
+    /// Whether `get_other_glyph` finds a glyph of that name, instead of the unknown glyph
+    pub fn has_other_glyph(&self, s: &str) -> bool {
+        self.other_map.contains_key(s)
     }
 
     pub fn get_glyph(&self, c: char) -> &[Ev] {
@@ -51,6 +179,419 @@ impl Alphabet {
             .get(&(s.to_owned()))
             .unwrap_or(&self.unknown_glyph)
     }
+
+    /// The first glyph the board can't play, named by what it's looked up with
+    pub fn check(&self, profile: &Profile) -> Result<(), (String, GlyphError)> {
+        self.check_with(|glyph| profile.check_glyph(glyph))
+    }
+
+    /// The first glyph `check` rejects, named by what it's looked up with
+    pub fn check_with(
+        &self,
+        check: impl Fn(&[Ev]) -> Result<(), GlyphError>,
+    ) -> Result<(), (String, GlyphError)> {
+        for (name, glyph) in self.named_glyphs() {
+            check(glyph).map_err(|e| (name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Every glyph named by what it's looked up with, always in the same order
+    fn named_glyphs(&self) -> Vec<(String, &[Ev])> {
+        let mut chars: Vec<_> = self.char_map.iter().collect();
+        chars.sort_by_key(|&(c, _)| *c);
+        let mut others: Vec<_> = self.other_map.iter().collect();
+        others.sort_by_key(|&(s, _)| name_order(s));
+        let ascii = (' '..='~').zip(self.ascii_block.iter());
+        ascii
+            .chain(chars.into_iter().map(|(c, g)| (*c, g)))
+            .map(|(c, g)| (toml_string(&c.to_string()), g.as_slice()))
+            .chain(others.into_iter().map(|(s, g)| (s.clone(), g.as_slice())))
+            .chain(iter::once((
+                "unknown".to_owned(),
+                self.unknown_glyph.as_slice(),
+            )))
+            .collect()
+    }
+
+    /// Stores each distinct glyph on the device once, as `prepare` turns it into, in place of
+    /// whatever the device stored before
+    pub fn upload(
+        &self,
+        dev: &mut dyn TactileDevice,
+        prepare: impl Fn(&[Ev]) -> anyhow::Result<Vec<Ev>>,
+    ) -> anyhow::Result<StoredAlphabet> {
+        let stored = self.distinct(prepare)?;
+        stored.upload(dev)?;
+        Ok(stored)
+    }
+
+    /// Each distinct glyph once, as `prepare` turns it into, numbered in the order of
+    /// `named_glyphs`
+    fn distinct(
+        &self,
+        prepare: impl Fn(&[Ev]) -> anyhow::Result<Vec<Ev>>,
+    ) -> anyhow::Result<StoredAlphabet> {
+        let mut stored = StoredAlphabet::default();
+        for (name, glyph) in self.named_glyphs() {
+            let glyph = prepare(glyph).map_err(|e| anyhow!("Glyph {}: {}", name, e))?;
+            if !stored.ids.contains_key(&glyph) {
+                let id = stored.glyphs.len() as u16;
+                stored.ids.insert(glyph.clone(), id);
+                stored.glyphs.insert(id, glyph);
+            }
+        }
+        Ok(stored)
+    }
+
+    /// The alphabet as an alphabet file that `load` reads back the same. Glyphs are written in the
+    /// short notation where it can say them, and event by event where it can't, paths as the
+    /// motors they passed on the board the alphabet was found for. Characters that play the unknown
+    /// glyph are left out.
+    pub fn to_toml(&self) -> String {
+        let mut out = format!("name = {}\n", toml_string(&self.name));
+        if self.unknown_glyph != Alphabet::default().unknown_glyph {
+            writeln!(out, "unknown = {}", glyph_toml(&self.unknown_glyph)).unwrap();
+        }
+        let mut chars: Vec<(char, &Vec<Ev>)> = (' '..='~').zip(self.ascii_block.iter()).collect();
+        chars.extend(self.char_map.iter().map(|(c, g)| (*c, g)));
+        chars.sort_by_key(|&(c, _)| c);
+        chars.retain(|&(_, g)| *g != self.unknown_glyph);
+        if !chars.is_empty() {
+            out.push_str("\n[chars]\n");
+            for (c, glyph) in chars {
+                writeln!(out, "{} = {}", toml_key(&c.to_string()), glyph_toml(glyph)).unwrap();
+            }
+        }
+        let mut named: Vec<_> = self.other_map.iter().collect();
+        named.sort_by_key(|&(name, _)| name_order(name));
+        if !named.is_empty() {
+            out.push_str("\n[named]\n");
+            for (name, glyph) in named {
+                writeln!(out, "{} = {}", toml_key(name), glyph_toml(glyph)).unwrap();
+            }
+        }
+        out
+    }
+
+    /// The alphabet as a C header, for the firmware's standalone demo mode to play without a
+    /// host. Each distinct glyph is kept once as its PKT_GLYPH payload, under the id `upload`
+    /// stores it with, and looked up by character or name through tables of those ids.
+    pub fn to_c_header(&self) -> String {
+        let glyphs = self
+            .distinct(|glyph| Ok(glyph.to_vec()))
+            .expect("glyphs are only copied");
+        let prefix = c_identifier(&self.name);
+        let upper = prefix.to_ascii_uppercase();
+        let id = |glyph: &[Ev]| glyphs.id(glyph).expect("every glyph has an id");
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "/* The {} alphabet, generated by tactom-experiments export-alphabet. Don't",
+            c_comment(&self.name)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            " * edit it, edit the alphabet file and export it again. */"
+        )
+        .unwrap();
+        writeln!(out, "#ifndef TACTOM_ALPHABET_{}_H", upper).unwrap();
+        writeln!(out, "#define TACTOM_ALPHABET_{}_H\n", upper).unwrap();
+        writeln!(out, "#include <stdint.h>\n").unwrap();
+        writeln!(
+            out,
+            "/* Glyphs are PKT_GLYPH payloads of this protocol version */"
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "#define {}_PROTOCOL_VERSION {}",
+            upper, PROTOCOL_VERSION
+        )
+        .unwrap();
+        writeln!(out, "#define {}_GLYPH_COUNT {}", upper, glyphs.glyphs.len()).unwrap();
+        writeln!(
+            out,
+            "/* Played for characters and names the alphabet doesn't have */"
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "#define {}_UNKNOWN {}\n",
+            upper,
+            id(&self.unknown_glyph)
+        )
+        .unwrap();
+
+        let mut data = vec![];
+        let mut spans = vec![];
+        for glyph in glyphs.glyphs.values() {
+            let payload = glyph_payload(glyph);
+            spans.push((data.len(), payload.len()));
+            data.extend(payload);
+        }
+        writeln!(out, "/* Every glyph's payload, back to back */").unwrap();
+        writeln!(out, "static const uint8_t {}_glyph_data[] = {{", prefix).unwrap();
+        for line in data.chunks(12) {
+            let bytes: Vec<String> = line.iter().map(|b| format!("0x{:02x}", b)).collect();
+            writeln!(out, "    {},", bytes.join(", ")).unwrap();
+        }
+        writeln!(out, "}};\n").unwrap();
+        writeln!(
+            out,
+            "/* Offset into {}_glyph_data and length of each glyph, by id */",
+            prefix
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "static const uint32_t {}_glyphs[{}_GLYPH_COUNT][2] = {{",
+            prefix, upper
+        )
+        .unwrap();
+        for (offset, len) in spans {
+            writeln!(out, "    {{{}, {}}},", offset, len).unwrap();
+        }
+        writeln!(out, "}};\n").unwrap();
+
+        writeln!(out, "/* Glyph id of each character from ' ' to '~' */").unwrap();
+        writeln!(out, "static const uint16_t {}_ascii[95] = {{", prefix).unwrap();
+        for line in self.ascii_block.chunks(12) {
+            let ids: Vec<String> = line.iter().map(|glyph| id(glyph).to_string()).collect();
+            writeln!(out, "    {},", ids.join(", ")).unwrap();
+        }
+        writeln!(out, "}};\n").unwrap();
+
+        let mut chars: Vec<_> = self.char_map.iter().collect();
+        chars.sort_by_key(|&(c, _)| *c);
+        chars.retain(|&(_, glyph)| *glyph != self.unknown_glyph);
+        writeln!(
+            out,
+            "/* Other characters, by Unicode code point, the rest play {}_UNKNOWN */",
+            upper
+        )
+        .unwrap();
+        writeln!(out, "#define {}_CHAR_COUNT {}", upper, chars.len()).unwrap();
+        if !chars.is_empty() {
+            writeln!(
+                out,
+                "static const uint32_t {}_char_codes[{}_CHAR_COUNT] = {{",
+                prefix, upper
+            )
+            .unwrap();
+            for (c, _) in &chars {
+                let shown = c_comment(&c.to_string());
+                writeln!(out, "    0x{:04x}, /* {} */", **c as u32, shown).unwrap();
+            }
+            writeln!(out, "}};").unwrap();
+            writeln!(
+                out,
+                "static const uint16_t {}_chars[{}_CHAR_COUNT] = {{",
+                prefix, upper
+            )
+            .unwrap();
+            for (_, glyph) in &chars {
+                writeln!(out, "    {},", id(glyph)).unwrap();
+            }
+            writeln!(out, "}};").unwrap();
+        }
+        out.push('\n');
+
+        let mut named: Vec<_> = self.other_map.iter().collect();
+        named.sort_by_key(|&(name, _)| name_order(name));
+        writeln!(out, "/* Glyphs looked up by name */").unwrap();
+        writeln!(out, "#define {}_NAMED_COUNT {}", upper, named.len()).unwrap();
+        if !named.is_empty() {
+            writeln!(
+                out,
+                "static const char *const {}_names[{}_NAMED_COUNT] = {{",
+                prefix, upper
+            )
+            .unwrap();
+            for (name, _) in &named {
+                writeln!(out, "    {},", c_string(name)).unwrap();
+            }
+            writeln!(out, "}};").unwrap();
+            writeln!(
+                out,
+                "static const uint16_t {}_named[{}_NAMED_COUNT] = {{",
+                prefix, upper
+            )
+            .unwrap();
+            for (_, glyph) in &named {
+                writeln!(out, "    {},", id(glyph)).unwrap();
+            }
+            writeln!(out, "}};").unwrap();
+        }
+        writeln!(out, "\n#endif").unwrap();
+        out
+    }
+}
+
+/// Sorts numbered glyphs first and by their number, the others by name
+fn name_order(name: &str) -> (u32, &str) {
+    (name.parse().unwrap_or(u32::MAX), name)
+}
+
+/// A key of an alphabet file, quoted unless TOML takes it bare
+fn toml_key(key: &str) -> String {
+    let bare = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if !key.is_empty() && key.chars().all(bare) {
+        key.to_owned()
+    } else {
+        toml_string(key)
+    }
+}
+
+/// A TOML basic string, with the escapes TOML has rather than Rust's
+fn toml_string(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04X}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A C string literal, everything but printable ASCII escaped byte by byte. Octal escapes end
+/// after 3 digits, so unlike hex ones they can't run into a digit that follows.
+fn c_string(text: &str) -> String {
+    let mut out = String::from('"');
+    for &b in text.as_bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(b as char),
+            b => write!(out, "\\{:03o}", b).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Text to put inside a C comment, control characters shown by code point and nothing that would
+/// end the comment
+fn c_comment(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if c.is_control() {
+            write!(out, "U+{:04X}", c as u32).unwrap();
+        } else {
+            out.push(c);
+        }
+    }
+    out.replace("*/", "* /")
+}
+
+/// The alphabet's name made into a C identifier for the header's names, lower case, with anything
+/// C doesn't take in one as _
+fn c_identifier(name: &str) -> String {
+    let ident: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    // C reserves names starting with _, and none start with a digit
+    let ident = ident.trim_matches('_');
+    if ident.is_empty() {
+        "alphabet".to_owned()
+    } else if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("alphabet_{}", ident)
+    } else {
+        ident.to_owned()
+    }
+}
+
+/// A glyph the way an alphabet file writes it
+fn glyph_toml(glyph: &[Ev]) -> String {
+    if let Some(text) = format_glyph(glyph) {
+        return toml::Value::String(text).to_string();
+    }
+    let events = glyph
+        .iter()
+        .filter_map(|ev| {
+            let (motor, chord) = match ev.ev_type {
+                EvType::Motor(motor) => (Some(motor), None),
+                EvType::Chord(motors) => (None, Some(motors.iter().collect())),
+                EvType::EndGlyph => return None,
+            };
+            Some(EventDef {
+                ms: ev.ms_time,
+                motor,
+                chord,
+                intensity: (ev.intensity != FULL_INTENSITY).then_some(ev.intensity),
+                pulse_ms: (ev.pulse_ms != DEFAULT_PULSE_MS).then_some(ev.pulse_ms),
+            })
+        })
+        .collect();
+    let def = GlyphDef {
+        events: Some(events),
+        end_ms: Some(glyph_duration(glyph)),
+        ..GlyphDef::default()
+    };
+    // Written inline, with the fields in the order GlyphDef and EventDef give them
+    let mut text = String::new();
+    def.serialize(toml::ser::ValueSerializer::new(&mut text))
+        .expect("a glyph always makes a TOML table");
+    text
+}
+
+/// The glyphs `Alphabet::upload` stored on a device, by id
+#[derive(Default)]
+pub struct StoredAlphabet {
+    glyphs: BTreeMap<u16, Vec<Ev>>,
+    ids: HashMap<Vec<Ev>, u16>,
+}
+
+impl StoredAlphabet {
+    /// The id a glyph is stored under, None if it wasn't stored
+    pub fn id(&self, glyph: &[Ev]) -> Option<u16> {
+        self.ids.get(glyph).copied()
+    }
+
+    /// What the device reports once it holds the glyphs
+    pub fn summary(&self) -> StoreSummary {
+        StoreSummary {
+            count: self.glyphs.len() as u16,
+            checksum: store_checksum(&self.glyphs),
+        }
+    }
+
+    /// Stores the glyphs on a device in place of whatever it stored before, e.g. again after it
+    /// was reset, and checks that it holds exactly them
+    pub fn upload(&self, dev: &mut dyn TactileDevice) -> anyhow::Result<()> {
+        dev.clear_stored()?;
+        for (&id, glyph) in &self.glyphs {
+            dev.store_glyph(id, glyph)
+                .map_err(|e| anyhow!("Couldn't store glyph {}: {}", id, e))?;
+        }
+        let held = dev.stored_glyphs()?;
+        if held != self.summary() {
+            return Err(anyhow!(
+                "The device stores {} instead of {}",
+                held,
+                self.summary()
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// linear rgb
@@ -86,16 +627,32 @@ fn color_interpolate(c1: (u8, u8, u8), c2: (u8, u8, u8), f: f32) -> (u8, u8, u8)
     )
 }
 
-pub fn println_glyph(glyph: &[Ev]) {
+/// Sorted distinct coordinates along one axis, each one becomes a row or column when printing
+fn grid_lines(coords: impl Iterator<Item = f32>) -> Vec<f32> {
+    let mut lines: Vec<f32> = coords.collect();
+    // Profiles only load with finite coordinates
+    lines.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    lines.dedup();
+    lines
+}
+
+pub fn println_glyph(profile: &Profile, glyph: &[Ev]) {
+    println!("{}", glyph_table(profile, glyph));
+}
+
+/// The board's motors laid out as a table, each one listing when the glyph plays it
+fn glyph_table(profile: &Profile, glyph: &[Ev]) -> String {
     let c1 = (0, 255, 0);
     let c2 = (0, 0, 255);
-    let mut places: Vec<Vec<usize>> = vec![];
-    for _ in 0..=(EndGlyph as usize) {
-        places.push(vec![]);
-    }
+    let mut places: Vec<Vec<usize>> = vec![vec![]; profile.motors.len()];
     let mut idx = 0;
     for ev in glyph {
-        places[ev.ev_type as usize].push(idx);
+        // A chord shows up in every motor it plays
+        for motor in ev.motors().iter() {
+            if let Some(place) = places.get_mut(motor as usize) {
+                place.push(idx);
+            }
+        }
         idx += 1;
     }
 
@@ -103,19 +660,22 @@ pub fn println_glyph(glyph: &[Ev]) {
 
     let len = (idx - 1) as f32;
 
-    const ROWS: usize = 3;
-    const COLS: usize = 4;
-    for y in 0..ROWS {
-        let mut row = vec![];
-        for x in 0..COLS {
-            let mut uh = String::new();
-            for occ in places[x + y * COLS].iter() {
-                let c = color_interpolate(c1, c2, *occ as f32 / len);
-                uh += &occ.to_string().truecolor(c.0, c.1, c.2).to_string();
-                uh += "\n";
-            }
+    let cols = grid_lines(profile.motors.iter().map(|m| m.x));
+    let rows = grid_lines(profile.motors.iter().map(|m| m.y));
+    let mut cells = vec![vec![String::new(); cols.len()]; rows.len()];
+    for (motor, occs) in profile.motors.iter().zip(places) {
+        let y = rows.iter().position(|&y| y == motor.y).unwrap();
+        let x = cols.iter().position(|&x| x == motor.x).unwrap();
+        let uh = &mut cells[y][x];
+        for occ in occs {
+            let c = color_interpolate(c1, c2, occ as f32 / len);
+            *uh += &occ.to_string().truecolor(c.0, c.1, c.2).to_string();
+            *uh += "\n";
+        }
+    }
+    for mut row in cells {
+        for uh in row.iter_mut() {
             uh.pop();
-            row.push(uh);
         }
         tb_builder.push_record(row);
     }
@@ -124,291 +684,335 @@ pub fn println_glyph(glyph: &[Ev]) {
     table
         .with(Style::modern_rounded())
         .with(width::Justify::max());
-    println!("{}", table);
+    table.to_string()
 }
 
-fn equal_spaced_evs(evs: &[u8], space_ms: u16) -> Vec<Ev> {
-    evs.iter()
-        .cloned()
-        .chain(iter::once(EndGlyph as u8))
+pub(crate) fn equal_spaced_evs(motors: &[u8], space_ms: u16) -> Vec<Ev> {
+    let end = Ev::end(motors.len() as u16 * space_ms);
+    motors
+        .iter()
         .enumerate()
-        .map(|(i, et)| Ev::new(i as u16 * space_ms, et))
+        .map(|(i, &motor)| Ev::motor(i as u16 * space_ms, motor))
+        .chain(iter::once(end))
         .collect()
 }
 
-pub fn retime_eq_spaced(glyph: &[Ev], space_ms: u16) -> Vec<Ev> {
+/// Like equal_spaced_evs, but every step plays a group of motors at once
+pub(crate) fn equal_spaced_chords(chords: &[&[u8]], space_ms: u16) -> Vec<Ev> {
+    let end = Ev::end(chords.len() as u16 * space_ms);
+    chords
+        .iter()
+        .enumerate()
+        .map(|(i, motors)| Ev::chord(i as u16 * space_ms, motors))
+        .chain(iter::once(end))
+        .collect()
+}
+
+/// None if the glyph would last too long for its times to fit in a u16
+pub fn retime_eq_spaced(glyph: &[Ev], space_ms: u16) -> Option<Vec<Ev>> {
     glyph
         .iter()
-        .map(|ev| ev.ev_type)
         .enumerate()
-        .map(|(i, et)| Ev::new(i as u16 * space_ms, et))
+        .map(|(i, ev)| {
+            let ms_time = u16::try_from(i).ok()?.checked_mul(space_ms)?;
+            Some(Ev { ms_time, ..*ev })
+        })
         .collect()
 }
 
-fn stitch_evs(glyphs: &[&[Ev]]) -> Vec<Ev> {
-    let mut out = vec![];
-    let mut time: u16 = 0;
-    let mut end_time: u16 = 0;
+/// Plays every pulse of a glyph at the same strength
+pub fn set_intensity(glyph: &[Ev], intensity: u8) -> Vec<Ev> {
+    glyph
+        .iter()
+        .map(|ev| ev.with_intensity(intensity))
+        .collect()
+}
+
+/// Gives every pulse of a glyph the same length
+pub fn set_pulse_ms(glyph: &[Ev], pulse_ms: u16) -> Vec<Ev> {
+    glyph.iter().map(|ev| ev.with_pulse_ms(pulse_ms)).collect()
+}
+
+/// None if the glyphs together last too long for one glyph
+pub(crate) fn stitch_evs(glyphs: &[&[Ev]]) -> Option<Vec<Ev>> {
+    let mut timeline = Timeline::default();
     for g in glyphs {
-        for mut ev in g.iter().cloned() {
-            if ev.ev_type == EndGlyph as u8 {
-                end_time = time + ev.ms_time;
-                time += ev.ms_time;
-            } else {
-                ev.ms_time += time;
-                out.push(ev);
-            }
-        }
+        timeline.push_glyph(g);
     }
-    out.push(Ev::new(end_time, EndGlyph as u8));
-    out
+    timeline.to_glyph()
 }
 
 pub fn glyph_duration(glyph: &[Ev]) -> u16 {
     glyph.last().map(|ev| ev.ms_time).unwrap_or(0)
 }
 
-pub fn init_alphabets() -> HashMap<String, Alphabet> {
-    let mut map = HashMap::new();
-
-    let mut distinguish = Alphabet::default();
-    for i in 0..12 {
-        distinguish
-            .other_map
-            .insert(i.to_string(), equal_spaced_evs(&[i], 30));
-    }
-    distinguish.add_other_glyphs(vec![
-        ("col0_up", equal_spaced_evs(&[8, 4, 0], 30)),
-        ("col1_up", equal_spaced_evs(&[9, 5, 1], 30)),
-        ("col2_up", equal_spaced_evs(&[10, 6, 2], 30)),
-        ("col3_up", equal_spaced_evs(&[11, 7, 3], 30)),
-        ("col0_down", equal_spaced_evs(&[0, 4, 8], 30)),
-        ("col1_down", equal_spaced_evs(&[1, 5, 9], 30)),
-        ("col2_down", equal_spaced_evs(&[2, 6, 10], 30)),
-        ("col3_down", equal_spaced_evs(&[3, 7, 11], 30)),
-        ("row0_right", equal_spaced_evs(&[0, 1, 2, 3], 30)),
-        ("row1_right", equal_spaced_evs(&[4, 5, 6, 7], 30)),
-        ("row2_right", equal_spaced_evs(&[8, 9, 10, 11], 30)),
-        ("row0_left", equal_spaced_evs(&[3, 2, 1, 0], 30)),
-        ("row1_left", equal_spaced_evs(&[7, 6, 5, 4], 30)),
-        ("row2_left", equal_spaced_evs(&[11, 10, 9, 8], 30)),
-        (
-            "clockwise",
-            equal_spaced_evs(&[0, 1, 2, 3, 7, 11, 10, 9, 8, 4, 0], 30),
-        ),
-        (
-            "anticlockwise",
-            equal_spaced_evs(&[0, 4, 8, 9, 10, 11, 7, 3, 2, 1, 0], 30),
-        ),
-        ("slash", equal_spaced_evs(&[3, 6, 5, 8], 30)),
-        ("rev_slash", equal_spaced_evs(&[8, 5, 6, 3], 30)),
-        ("backslash", equal_spaced_evs(&[0, 5, 6, 11], 30)),
-        ("rev_backslash", equal_spaced_evs(&[11, 6, 5, 0], 30)),
-    ]);
-    distinguish.add_other_glyphs(vec![
-        (
-            "N",
-            stitch_evs(&[
-                distinguish.get_other_glyph("col0_up"),
-                distinguish.get_other_glyph("col1_down"),
-                distinguish.get_other_glyph("col2_up"),
-            ]),
-        ),
-        (
-            "flipped_N",
-            stitch_evs(&[
-                distinguish.get_other_glyph("col2_up"),
-                distinguish.get_other_glyph("col1_down"),
-                distinguish.get_other_glyph("col0_up"),
-            ]),
-        ),
-        (
-            "zig",
-            stitch_evs(&[
-                distinguish.get_other_glyph("row0_right"),
-                distinguish.get_other_glyph("row1_left"),
-                distinguish.get_other_glyph("row2_right"),
-            ]),
-        ),
-        (
-            "zag",
-            stitch_evs(&[
-                distinguish.get_other_glyph("row0_left"),
-                distinguish.get_other_glyph("row1_right"),
-                distinguish.get_other_glyph("row2_left"),
-            ]),
-        ),
-    ]);
-    distinguish.add_other_glyphs(vec![
-        ("col0_up_dropout0", equal_spaced_evs(&[8, 8, 0], 30)),
-        ("col0_up_dropout1", equal_spaced_evs(&[8, 0, 0], 30)),
-        ("col2_down_dropout0", equal_spaced_evs(&[2, 2, 10], 30)),
-        ("col2_down_dropout1", equal_spaced_evs(&[2, 10, 10], 30)),
-        ("row0_left_dropout0", equal_spaced_evs(&[3, 3, 1, 0], 30)),
-        ("row0_left_dropout1", equal_spaced_evs(&[3, 2, 2, 0], 30)),
-        ("row0_left_dropout2", equal_spaced_evs(&[3, 1, 1, 0], 30)),
-        ("row0_left_dropout3", equal_spaced_evs(&[3, 2, 0, 0], 30)),
-        ("row0_left_dropout4", equal_spaced_evs(&[3, 3, 0, 0], 30)),
-        ("row1_right_dropout0", equal_spaced_evs(&[4, 4, 6, 7], 30)),
-        ("row1_right_dropout1", equal_spaced_evs(&[4, 5, 5, 7], 30)),
-        ("row1_right_dropout2", equal_spaced_evs(&[4, 6, 6, 7], 30)),
-        ("row1_right_dropout3", equal_spaced_evs(&[4, 5, 7, 7], 30)),
-        ("row1_right_dropout4", equal_spaced_evs(&[4, 4, 7, 7], 30)),
-        ("row2_right_dropout0", equal_spaced_evs(&[8, 8, 10, 11], 30)),
-        ("row2_right_dropout1", equal_spaced_evs(&[8, 9, 9, 11], 30)),
-        (
-            "row2_right_dropout2",
-            equal_spaced_evs(&[8, 10, 10, 11], 30),
-        ),
-        ("row2_right_dropout3", equal_spaced_evs(&[8, 9, 11, 11], 30)),
-        ("row2_right_dropout4", equal_spaced_evs(&[8, 8, 11, 11], 30)),
-        (
-            "clockwise_dropout0",
-            equal_spaced_evs(&[0, 1, 1, 3, 7, 11, 10, 9, 8, 4, 0], 30),
-        ),
-        (
-            "clockwise_dropout1",
-            equal_spaced_evs(&[0, 1, 2, 3, 3, 11, 10, 9, 8, 4, 0], 30),
-        ),
-        (
-            "clockwise_dropout2",
-            equal_spaced_evs(&[0, 1, 2, 3, 7, 11, 11, 9, 8, 4, 0], 30),
-        ),
-        (
-            "clockwise_dropout3",
-            equal_spaced_evs(&[0, 1, 2, 3, 7, 11, 10, 9, 9, 4, 0], 30),
-        ),
-        (
-            "clockwise_dropout4",
-            equal_spaced_evs(&[0, 1, 2, 3, 7, 11, 10, 9, 8, 0, 0], 30),
-        ),
-        (
-            "anticlockwise_dropout0",
-            equal_spaced_evs(&[0, 4, 8, 8, 10, 11, 7, 3, 2, 1, 0], 30),
-        ),
-        (
-            "anticlockwise_dropout1",
-            equal_spaced_evs(&[0, 4, 8, 9, 10, 10, 7, 3, 2, 1, 0], 30),
-        ),
-        (
-            "anticlockwise_dropout2",
-            equal_spaced_evs(&[0, 4, 8, 9, 10, 11, 3, 3, 2, 1, 0], 30),
-        ),
-        (
-            "anticlockwise_dropout3",
-            equal_spaced_evs(&[0, 4, 8, 9, 10, 11, 7, 3, 2, 2, 0], 30),
-        ),
-        (
-            "anticlockwise_dropout4",
-            equal_spaced_evs(&[0, 0, 8, 9, 10, 11, 7, 3, 2, 1, 0], 30),
-        ),
-    ]);
-    map.insert("distinguish".to_owned(), distinguish);
-
-    let mut roud_graff = Alphabet::default(); // short for "Roudaut graffiti"
-    for i in 0..12 {
-        roud_graff
-            .other_map
-            .insert(i.to_string(), equal_spaced_evs(&[i], 100));
-    }
-    roud_graff.ascii_block = vec![
-        roud_graff.unknown_glyph.to_owned(),      // <space>
-        roud_graff.unknown_glyph.to_owned(),      // !
-        roud_graff.unknown_glyph.to_owned(),      // "
-        roud_graff.unknown_glyph.to_owned(),      // #
-        roud_graff.unknown_glyph.to_owned(),      // $
-        roud_graff.unknown_glyph.to_owned(),      // %
-        roud_graff.unknown_glyph.to_owned(),      // &
-        roud_graff.unknown_glyph.to_owned(),      // '
-        roud_graff.unknown_glyph.to_owned(),      // (
-        roud_graff.unknown_glyph.to_owned(),      // )
-        roud_graff.unknown_glyph.to_owned(),      // *
-        roud_graff.unknown_glyph.to_owned(),      // +
-        roud_graff.unknown_glyph.to_owned(),      // ,
-        roud_graff.unknown_glyph.to_owned(),      // -
-        roud_graff.unknown_glyph.to_owned(),      // .
-        roud_graff.unknown_glyph.to_owned(),      // /
-        roud_graff.unknown_glyph.to_owned(),      // 0
-        roud_graff.unknown_glyph.to_owned(),      // 1
-        roud_graff.unknown_glyph.to_owned(),      // 2
-        roud_graff.unknown_glyph.to_owned(),      // 3
-        roud_graff.unknown_glyph.to_owned(),      // 4
-        roud_graff.unknown_glyph.to_owned(),      // 5
-        roud_graff.unknown_glyph.to_owned(),      // 6
-        roud_graff.unknown_glyph.to_owned(),      // 7
-        roud_graff.unknown_glyph.to_owned(),      // 8
-        roud_graff.unknown_glyph.to_owned(),      // 9
-        roud_graff.unknown_glyph.to_owned(),      // :
-        roud_graff.unknown_glyph.to_owned(),      // ;
-        roud_graff.unknown_glyph.to_owned(),      // <
-        roud_graff.unknown_glyph.to_owned(),      // =
-        roud_graff.unknown_glyph.to_owned(),      // >
-        roud_graff.unknown_glyph.to_owned(),      // ?
-        roud_graff.unknown_glyph.to_owned(),      // @
-        roud_graff.unknown_glyph.to_owned(),      // A
-        roud_graff.unknown_glyph.to_owned(),      // B
-        roud_graff.unknown_glyph.to_owned(),      // C
-        roud_graff.unknown_glyph.to_owned(),      // D
-        roud_graff.unknown_glyph.to_owned(),      // E
-        roud_graff.unknown_glyph.to_owned(),      // F
-        roud_graff.unknown_glyph.to_owned(),      // G
-        roud_graff.unknown_glyph.to_owned(),      // H
-        roud_graff.unknown_glyph.to_owned(),      // I
-        roud_graff.unknown_glyph.to_owned(),      // J
-        roud_graff.unknown_glyph.to_owned(),      // K
-        roud_graff.unknown_glyph.to_owned(),      // L
-        roud_graff.unknown_glyph.to_owned(),      // M
-        roud_graff.unknown_glyph.to_owned(),      // N
-        roud_graff.unknown_glyph.to_owned(),      // O
-        roud_graff.unknown_glyph.to_owned(),      // P
-        roud_graff.unknown_glyph.to_owned(),      // Q
-        roud_graff.unknown_glyph.to_owned(),      // R
-        roud_graff.unknown_glyph.to_owned(),      // S
-        roud_graff.unknown_glyph.to_owned(),      // T
-        roud_graff.unknown_glyph.to_owned(),      // U
-        roud_graff.unknown_glyph.to_owned(),      // V
-        roud_graff.unknown_glyph.to_owned(),      // W
-        roud_graff.unknown_glyph.to_owned(),      // X
-        roud_graff.unknown_glyph.to_owned(),      // Y
-        roud_graff.unknown_glyph.to_owned(),      // Z
-        roud_graff.unknown_glyph.to_owned(),      // [
-        roud_graff.unknown_glyph.to_owned(),      // \
-        roud_graff.unknown_glyph.to_owned(),      // ]
-        roud_graff.unknown_glyph.to_owned(),      // ^
-        roud_graff.unknown_glyph.to_owned(),      // _
-        roud_graff.unknown_glyph.to_owned(),      // `
-        equal_spaced_evs(&[8, 4, 1, 6, 11], 150), // a
-        equal_spaced_evs(&[0, 4, 8, 4, 5, 6, 7, 11, 10, 9, 8], 150), // b
-        equal_spaced_evs(&[3, 2, 1, 0, 4, 8, 9, 10, 11], 150), // c
-        equal_spaced_evs(&[8, 4, 0, 1, 2, 3, 7, 11, 10, 9, 8], 150), // d
-        equal_spaced_evs(&[4, 5, 6, 7, 3, 2, 1, 0, 4, 8, 9, 10, 11], 150), // e
-        equal_spaced_evs(&[3, 2, 1, 0, 4, 8], 150), // f
-        equal_spaced_evs(&[1, 0, 4, 8, 9, 10, 11, 7, 6], 150), // g
-        equal_spaced_evs(&[0, 4, 8, 4, 5, 6, 7, 11], 150), // h
-        equal_spaced_evs(&[0, 4, 8], 150),        // i
-        equal_spaced_evs(&[3, 7, 11, 10, 9, 8], 150), // j
-        equal_spaced_evs(&[3, 6, 9, 8, 4, 0, 1, 6, 11], 150), // k
-        equal_spaced_evs(&[0, 4, 8, 9, 10, 11], 150), // l
-        equal_spaced_evs(&[8, 4, 0, 1, 5, 6, 2, 3, 7, 11], 150), // m
-        equal_spaced_evs(&[8, 4, 0, 1, 5, 10, 11, 7, 3], 150), // n
-        equal_spaced_evs(&[1, 0, 4, 8, 9, 10, 11, 7, 3, 2], 150), // o
-        equal_spaced_evs(&[8, 4, 0, 1, 2, 3, 7, 6, 5, 4], 150), // p
-        equal_spaced_evs(&[3, 2, 1, 0, 4, 5, 6, 7, 3, 7, 11], 150), // q
-        equal_spaced_evs(&[8, 4, 0, 1, 2, 3, 7, 6, 5, 4, 5, 10], 150), // r
-        equal_spaced_evs(&[3, 2, 1, 0, 4, 5, 6, 7, 11, 10, 9, 8], 150), // s
-        equal_spaced_evs(&[0, 1, 2, 3, 7, 11], 150), // t
-        equal_spaced_evs(&[0, 4, 8, 9, 10, 11, 7, 3], 150), // u
-        equal_spaced_evs(&[0, 4, 9, 6, 3], 150),  // v
-        equal_spaced_evs(&[0, 4, 8, 9, 5, 6, 10, 11, 7, 3], 150), // w
-        equal_spaced_evs(&[0, 5, 10, 6, 2, 5, 8], 150), // x
-        equal_spaced_evs(&[0, 4, 5, 6, 7, 3, 7, 11, 10, 9, 6, 3], 150), // y
-        equal_spaced_evs(&[0, 1, 2, 3, 7, 6, 5, 4, 8, 9, 10, 11], 150), // z
-        roud_graff.unknown_glyph.to_owned(),      // {
-        roud_graff.unknown_glyph.to_owned(),      // |
-        roud_graff.unknown_glyph.to_owned(),      // }
-        roud_graff.unknown_glyph.to_owned(),      // ~
-    ];
-    map.insert("roud_graff".to_owned(), roud_graff);
-
-    map
+/// An alphabet file as written, see alphabets/distinguish.toml
+#[derive(Deserialize)]
+struct AlphabetFile {
+    name: String,
+    canvas: Option<Canvas>,
+    each_motor_ms: Option<u16>,
+    unknown: Option<GlyphSpec>,
+    #[serde(default)]
+    chars: BTreeMap<String, GlyphSpec>,
+    #[serde(default)]
+    named: BTreeMap<String, GlyphSpec>,
+}
+
+/// A glyph in an alphabet file, written out as a table or in the notation of
+/// `notation::parse_glyph`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GlyphSpec {
+    Notation(String),
+    Def(GlyphDef),
+}
+
+impl GlyphSpec {
+    /// Names of the glyphs it is stitched from
+    fn parts(&self) -> Vec<&str> {
+        match self {
+            GlyphSpec::Notation(text) => glyph_names(text),
+            GlyphSpec::Def(def) => def.stitch.iter().flatten().map(String::as_str).collect(),
+        }
+    }
+
+    /// Paths are traced over the board's motors, motors given by index aren't checked against it
+    /// here, `Alphabet::check` does that
+    fn build(
+        &self,
+        named: &HashMap<String, Vec<Ev>>,
+        canvas: Canvas,
+        profile: &Profile,
+    ) -> anyhow::Result<Vec<Ev>> {
+        match self {
+            GlyphSpec::Notation(text) => parse_glyph(text, EV_CHORD, named),
+            GlyphSpec::Def(def) => def.build(named, canvas, profile),
+        }
+    }
+}
+
+/// One glyph of an alphabet file, either motors, chords or the motors along a path `space_ms` apart,
+/// named glyphs played back to back, or events at any time
+#[derive(Default, Deserialize, Serialize)]
+struct GlyphDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    motors: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<Vec<Point>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chords: Option<Vec<Vec<u8>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    space_ms: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stitch: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_ms: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    events: Option<Vec<EventDef>>,
+}
+
+/// An event of a glyph written out in full, it plays either a motor or a chord
+#[derive(Deserialize, Serialize)]
+struct EventDef {
+    ms: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    motor: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chord: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    intensity: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pulse_ms: Option<u16>,
+}
+
+impl EventDef {
+    fn build(&self) -> anyhow::Result<Ev> {
+        let ev = match (self.motor, &self.chord) {
+            (Some(motor), None) => Ev::motor(self.ms, motor),
+            (None, Some(chord)) => Ev::chord(self.ms, chord),
+            _ => {
+                return Err(anyhow!(
+                    "the event at {} ms needs either motor or chord",
+                    self.ms
+                ))
+            }
+        };
+        let ev = ev.with_intensity(self.intensity.unwrap_or(FULL_INTENSITY));
+        Ok(ev.with_pulse_ms(self.pulse_ms.unwrap_or(DEFAULT_PULSE_MS)))
+    }
+}
+
+impl GlyphDef {
+    /// `named` are the glyphs a stitch can be made of, a path is drawn on `canvas`
+    fn build(
+        &self,
+        named: &HashMap<String, Vec<Ev>>,
+        canvas: Canvas,
+        profile: &Profile,
+    ) -> anyhow::Result<Vec<Ev>> {
+        let motors = match (&self.motors, &self.path) {
+            (Some(_), Some(_)) => return Err(anyhow!("give either motors or a path, not both")),
+            (None, Some(path)) => Some(trace_path(path, canvas, profile)?),
+            (motors, None) => motors.clone(),
+        };
+        if let (Some(events), Some(end_ms)) = (&self.events, self.end_ms) {
+            if motors.is_some() || self.chords.is_some() || self.stitch.is_some() {
+                return Err(anyhow!(
+                    "events can't go with motors, a path, chords or stitch"
+                ));
+            }
+            let events = events.iter().map(EventDef::build);
+            return events.chain([Ok(Ev::end(end_ms))]).collect();
+        }
+        match (&motors, &self.chords, self.space_ms, &self.stitch) {
+            (Some(motors), None, Some(space_ms), None) => {
+                check_fits(motors.len(), space_ms)?;
+                Ok(equal_spaced_evs(motors, space_ms))
+            }
+            (None, Some(chords), Some(space_ms), None) => {
+                check_fits(chords.len(), space_ms)?;
+                let chords: Vec<&[u8]> = chords.iter().map(Vec::as_slice).collect();
+                Ok(equal_spaced_chords(&chords, space_ms))
+            }
+            (None, None, None, Some(names)) => {
+                let glyphs = names
+                    .iter()
+                    .map(|name| {
+                        named
+                            .get(name)
+                            .map(Vec::as_slice)
+                            .ok_or_else(|| anyhow!("there is no glyph called {}", name))
+                    })
+                    .collect::<anyhow::Result<Vec<&[Ev]>>>()?;
+                stitch_evs(&glyphs).ok_or_else(|| anyhow!("the stitched glyphs last too long"))
+            }
+            _ => Err(anyhow!(
+                "give either motors, chords or a path with space_ms, stitch, or events with end_ms"
+            )),
+        }
+    }
+}
+
+/// Whether `steps` steps `space_ms` apart are short enough for a glyph
+pub(crate) fn check_fits(steps: usize, space_ms: u16) -> anyhow::Result<()> {
+    if steps as u64 * space_ms as u64 > u16::MAX as u64 {
+        return Err(anyhow!("it would last longer than {} ms", u16::MAX));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Motor;
+
+    /// An alphabet whose name and glyph names need every kind of quoting
+    fn awkward_alphabet() -> Alphabet {
+        let mut a_bet = Alphabet {
+            name: "9 \"odd\" */ alphabet\u{1b}".to_owned(),
+            ..Alphabet::default()
+        };
+        a_bet.ascii_block[(b'"' - b' ') as usize] = equal_spaced_evs(&[1, 2], 30);
+        a_bet.ascii_block[(b'\\' - b' ') as usize] = equal_spaced_evs(&[3], 50);
+        a_bet
+            .char_map
+            .insert('\u{1b}', equal_spaced_evs(&[4, 5], 40));
+        a_bet.char_map.insert('\t', equal_spaced_evs(&[6], 40));
+        let quiet = Ev {
+            intensity: 90,
+            ..Ev::motor(0, 7)
+        };
+        a_bet.char_map.insert('é', vec![quiet, Ev::end(40)]);
+        for (i, name) in [
+            "12",
+            "with space",
+            "quote\"d",
+            "tab\there",
+            "ü",
+            "bell\u{7}",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let glyph = equal_spaced_evs(&[i as u8, 11 - i as u8], 35);
+            a_bet.other_map.insert(name.to_owned(), glyph);
+        }
+        a_bet.unknown_glyph = equal_spaced_evs(&[0, 11], 60);
+        a_bet
+    }
+
+    #[test]
+    fn glyph_table_follows_the_board() {
+        colored::control::set_override(false);
+        // Two columns of three motors, numbered row by row
+        let profile = Profile {
+            motors: (0..6)
+                .map(|i| Motor {
+                    x: (i % 2) as f32,
+                    y: (i / 2) as f32,
+                })
+                .collect(),
+            ..Profile::prototype()
+        };
+        let table = glyph_table(&profile, &equal_spaced_evs(&[0, 3, 5, 0], 30));
+        // Each cell lists the steps its motor plays in, motor 0 both the first and the last
+        let expected = [
+            "╭───┬───╮",
+            "│ 0 │   │",
+            "│ 3 │   │",
+            "├───┼───┤",
+            "│   │ 1 │",
+            "├───┼───┤",
+            "│   │ 2 │",
+            "╰───┴───╯",
+        ];
+        assert_eq!(table, expected.join("\n"));
+    }
+
+    #[test]
+    fn to_toml_parses_back_the_same() {
+        let a_bet = awkward_alphabet();
+        let text = a_bet.to_toml();
+        let parsed = Alphabet::parse(&text, &Profile::prototype()).unwrap();
+        assert_eq!(parsed.name, a_bet.name);
+        assert_eq!(parsed.ascii_block, a_bet.ascii_block);
+        assert_eq!(parsed.char_map, a_bet.char_map);
+        assert_eq!(parsed.other_map, a_bet.other_map);
+        assert_eq!(parsed.unknown_glyph, a_bet.unknown_glyph);
+    }
+
+    #[test]
+    fn toml_strings_use_toml_escapes() {
+        assert_eq!(toml_string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(toml_string("\u{1b}\n\u{7f}é"), r#""\u001B\n\u007Fé""#);
+        assert_eq!(toml_key("a-b_9"), "a-b_9");
+        assert_eq!(toml_key(""), r#""""#);
+        assert_eq!(toml_key("a b"), r#""a b""#);
+    }
+
+    #[test]
+    fn c_strings_and_comments_are_valid_c() {
+        assert_eq!(c_string("a\"b\\c"), r#""a\"b\\c""#);
+        // é is 0xc3 0xa9, and the octal escape doesn't run into the 1 after it
+        assert_eq!(c_string("é1\u{1b}\n"), r#""\303\2511\033\n""#);
+        assert_eq!(c_comment("a */ b\u{1b}"), "a * / bU+001B");
+    }
+
+    #[test]
+    fn header_names_are_c_identifiers() {
+        assert_eq!(c_identifier("roud_graff"), "roud_graff");
+        assert_eq!(c_identifier("My Letters"), "my_letters");
+        assert_eq!(c_identifier(""), "alphabet");
+        assert_eq!(c_identifier("?!"), "alphabet");
+        assert_eq!(c_identifier("9 dots"), "alphabet_9_dots");
+        assert_eq!(c_identifier("_private-é"), "private");
+        let header = awkward_alphabet().to_c_header();
+        assert!(header.contains("#ifndef TACTOM_ALPHABET_ALPHABET_9__ODD_____ALPHABET_H\n"));
+        assert!(header.contains("alphabet_9__odd_____alphabet_glyph_data[]"));
+        assert!(header.contains("    \"quote\\\"d\",\n"));
+        assert!(header.contains("    \"bell\\007\",\n"));
+        assert!(header.contains("    0x00e9, /* é */\n"));
+        assert!(header.contains("    0x001b, /* U+001B */\n"));
+    }
 }
@@ -0,0 +1,250 @@
+use std::{
+    sync::Barrier,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    capture::Capture,
+    device::{DeviceStatus, Playback, StopHandle, TactileDevice},
+    event::{Capabilities, Ev, StoreSummary, TimeScale},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    pub fn other(self) -> Hand {
+        match self {
+            Hand::Left => Hand::Right,
+            Hand::Right => Hand::Left,
+        }
+    }
+}
+
+impl std::fmt::Display for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Hand::Left => write!(f, "left"),
+            Hand::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// A device on each palm, sharing a clock so that their Playback times can be compared.
+///
+/// As a TactileDevice the pair plays every glyph on both palms at once, `play` puts different
+/// glyphs on each.
+pub struct DevicePair {
+    left: Box<dyn TactileDevice>,
+    right: Box<dyn TactileDevice>,
+    sent: [bool; 2], // Which palms the latest `play` sent to, left then right
+    playbacks: [Option<Playback>; 2],
+}
+
+impl DevicePair {
+    pub fn new(mut left: Box<dyn TactileDevice>, mut right: Box<dyn TactileDevice>) -> Self {
+        let origin = Instant::now();
+        left.set_clock_origin(origin);
+        right.set_clock_origin(origin);
+        Self {
+            left,
+            right,
+            sent: [false; 2],
+            playbacks: [None; 2],
+        }
+    }
+
+    pub fn hand(&mut self, hand: Hand) -> &mut dyn TactileDevice {
+        match hand {
+            Hand::Left => self.left.as_mut(),
+            Hand::Right => self.right.as_mut(),
+        }
+    }
+
+    /// Starts a glyph on each palm at the same moment, each one the way its call to the palm's
+    /// device starts it, e.g. sent in full or played from the store. A palm given None is left
+    /// alone. Both are written from their own thread, released together, so that neither waits
+    /// for the other's write.
+    pub fn play<L, R>(&mut self, left: Option<L>, right: Option<R>) -> anyhow::Result<()>
+    where
+        L: FnOnce(&mut dyn TactileDevice) -> anyhow::Result<()> + Send,
+        R: FnOnce(&mut dyn TactileDevice) -> anyhow::Result<()>,
+    {
+        self.sent = [left.is_some(), right.is_some()];
+        self.playbacks = [None; 2];
+        match (left, right) {
+            (Some(l), Some(r)) => self.at_once(l, r),
+            (Some(l), None) => l(self.left.as_mut()),
+            (None, Some(r)) => r(self.right.as_mut()),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Runs a call on each palm's device, each from its own thread, released together
+    fn at_once(
+        &mut self,
+        left: impl FnOnce(&mut dyn TactileDevice) -> anyhow::Result<()> + Send,
+        right: impl FnOnce(&mut dyn TactileDevice) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let start = Barrier::new(2);
+        let (left_dev, right_dev) = (self.left.as_mut(), self.right.as_mut());
+        let (left_res, right_res) = thread::scope(|s| {
+            let left_thread = s.spawn(|| {
+                start.wait();
+                left(left_dev)
+            });
+            start.wait();
+            let right_res = right(right_dev);
+            let left_res = left_thread
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("Left palm's writer panicked")));
+            (left_res, right_res)
+        });
+        left_res.and(right_res)
+    }
+
+    /// When each palm played the glyph `wait_playback` waited for last, left then right. None for
+    /// a palm that wasn't sent anything or can't report playback.
+    pub fn playbacks(&self) -> [Option<Playback>; 2] {
+        self.playbacks
+    }
+}
+
+impl TactileDevice for DevicePair {
+    fn send_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        let send = |dev: &mut dyn TactileDevice| dev.send_glyph(glyph);
+        self.play(Some(send), Some(send))
+    }
+
+    fn queue_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        let queue = |dev: &mut dyn TactileDevice| dev.queue_glyph(glyph);
+        self.play(Some(queue), Some(queue))
+    }
+
+    /// The earliest onset of the palms that were sent to
+    fn wait_onset(&mut self, timeout: Duration) -> anyhow::Result<Option<u64>> {
+        let deadline = Instant::now() + timeout;
+        let [send_left, send_right] = self.sent;
+        let mut onsets = vec![];
+        if send_left {
+            onsets.push(self.left.wait_onset(timeout)?);
+        }
+        if send_right {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            onsets.push(self.right.wait_onset(remaining)?);
+        }
+        let onsets: Option<Vec<u64>> = onsets.into_iter().collect();
+        Ok(onsets.and_then(|onsets| onsets.into_iter().min()))
+    }
+
+    /// Both palms keep the same glyphs
+    fn store_glyph(&mut self, id: u16, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.left.store_glyph(id, glyph)?;
+        self.right.store_glyph(id, glyph)
+    }
+
+    fn play_stored(&mut self, id: u16, scale: TimeScale) -> anyhow::Result<()> {
+        let play = |dev: &mut dyn TactileDevice| dev.play_stored(id, scale);
+        self.play(Some(play), Some(play))
+    }
+
+    /// What both palms keep, which has to be the same
+    fn stored_glyphs(&mut self) -> anyhow::Result<StoreSummary> {
+        let (left, right) = (self.left.stored_glyphs()?, self.right.stored_glyphs()?);
+        if left != right {
+            return Err(anyhow!(
+                "The palms' devices store different glyphs, left: {}, right: {}",
+                left,
+                right
+            ));
+        }
+        Ok(left)
+    }
+
+    fn clear_stored(&mut self) -> anyhow::Result<()> {
+        let left = self.left.clear_stored();
+        self.right.clear_stored().and(left)
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.left.send_raw(bytes)?;
+        self.right.send_raw(bytes)
+    }
+
+    /// A capture can only be written by one device, it records the right palm. Sessions on a pair
+    /// refuse --capture rather than silently leave the left palm out.
+    fn set_capture(&mut self, capture: Capture) {
+        self.right.set_capture(capture);
+    }
+
+    /// Waits for both palms, the playback spans from the first onset to the last offset
+    fn wait_playback(&mut self, timeout: Duration) -> anyhow::Result<Option<Playback>> {
+        let deadline = Instant::now() + timeout;
+        let [send_left, send_right] = self.sent;
+        if send_left {
+            self.playbacks[0] = self.left.wait_playback(timeout)?;
+        }
+        if send_right {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            self.playbacks[1] = self.right.wait_playback(remaining)?;
+        }
+        let played: Vec<Playback> = self.playbacks.iter().flatten().copied().collect();
+        if played.len() != self.sent.iter().filter(|&&sent| sent).count() || played.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Playback {
+            onset_ms: played.iter().map(|p| p.onset_ms).min().unwrap_or(0),
+            offset_ms: played.iter().map(|p| p.offset_ms).max().unwrap_or(0),
+        }))
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        let left = self.left.stop();
+        self.right.stop().and(left)
+    }
+
+    fn status(&mut self) -> anyhow::Result<DeviceStatus> {
+        match (self.left.status()?, self.right.status()?) {
+            (DeviceStatus::Playing, _) | (_, DeviceStatus::Playing) => Ok(DeviceStatus::Playing),
+            (DeviceStatus::Unknown, _) | (_, DeviceStatus::Unknown) => Ok(DeviceStatus::Unknown),
+            _ => Ok(DeviceStatus::Idle),
+        }
+    }
+
+    /// What both palms can do. The palms share a profile, so they have to agree on the protocol
+    /// and the motors.
+    fn capabilities(&mut self) -> anyhow::Result<Capabilities> {
+        let (left, right) = (self.left.capabilities()?, self.right.capabilities()?);
+        if left.protocol_version != right.protocol_version || left.motor_count != right.motor_count
+        {
+            return Err(anyhow!(
+                "The palms' devices don't match, left: {}, right: {}",
+                left,
+                right
+            ));
+        }
+        Ok(Capabilities {
+            max_glyph_len: left.max_glyph_len.min(right.max_glyph_len),
+            features: left.features & right.features,
+            ..left
+        })
+    }
+
+    fn set_clock_origin(&mut self, origin: Instant) {
+        self.left.set_clock_origin(origin);
+        self.right.set_clock_origin(origin);
+    }
+
+    fn stop_handle(&self) -> Option<StopHandle> {
+        match (self.left.stop_handle(), self.right.stop_handle()) {
+            (Some(left), Some(right)) => Some(left.join(right)),
+            (left, right) => left.or(right),
+        }
+    }
+}
@@ -0,0 +1,14 @@
+pub mod bench;
+pub mod capture;
+pub mod device;
+pub mod discover;
+pub mod event;
+pub mod geometry;
+pub mod glyphs;
+pub mod hands;
+pub mod notation;
+pub mod profile;
+pub mod reconnect;
+pub mod session;
+pub mod sim;
+pub mod stream;
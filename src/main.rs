@@ -1,36 +1,74 @@
 use std::{
-    fs::File,
+    collections::HashMap,
+    fs::{self, File},
     io::{self, stdin, Write},
     iter,
     path::{Path, PathBuf},
-    sync::Arc,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
-use clap::{Parser, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use csv::Writer;
-use event::{queue_events_as_raw, Ev};
-use glyphs::{glyph_duration, init_alphabets, println_glyph, retime_eq_spaced, Alphabet};
 use rand::{random, rng, seq::SliceRandom};
 use serde::Serialize;
-use serialport::TTYPort;
-use tokio::{sync::RwLock, time::sleep};
-
-mod event;
-mod glyphs;
+use tactom_experiments::{
+    bench::{self, Samples},
+    capture::{load_bytes, read_capture, Capture},
+    device::{DeviceStatus, Playback, SerialDevice, StopHandle, TactileDevice},
+    discover::{find_device, list_candidates},
+    event::{
+        decode_glyphs, Ev, Frame, TimeScale, DEFAULT_PULSE_MS, FEATURE_GLYPH_STORE,
+        FEATURE_INTENSITY, FEATURE_PULSE_MS, FULL_INTENSITY, PROTOCOL_VERSION,
+    },
+    glyphs::{
+        glyph_duration, println_glyph, retime_eq_spaced, set_intensity, set_pulse_ms, Alphabet,
+        StoredAlphabet,
+    },
+    hands::{DevicePair, Hand},
+    profile::Profile,
+    reconnect::{Reconnected, ReconnectingDevice},
+    session::SessionLog,
+    sim::SimDevice,
+    stream::{self, Timeline},
+};
+use tokio::{
+    signal::ctrl_c,
+    sync::RwLock,
+    task::{block_in_place, spawn_blocking},
+    time::sleep,
+};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Exp {
     Dropout,
     Draw,
     Alphabet,
+    CrossHand,
 }
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 struct Cli {
-    /// serial device to interface with tactom device
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: Option<RunArgs>,
+    /// .toml file describing the board's motors and serial link, defaults to the 3x4 prototype
+    #[arg(long, global = true, value_name = "PROFILE")]
+    profile: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// serial device to interface with tactom device, "auto" to find it or "sim" for the software
+    /// simulator
     #[arg(value_name = "TTY_DEV")]
     tty_path: PathBuf,
     /// Which experiment to run
@@ -39,6 +77,92 @@ struct Cli {
     /// .csv file to record data to
     #[arg(value_name = "OUTPUT_FILE")]
     out_path: PathBuf,
+    /// Record every byte sent to the device, with timestamps, to this file. Only for sessions on
+    /// one device.
+    #[arg(long, value_name = "CAPTURE_FILE", conflicts_with = "left")]
+    capture: Option<PathBuf>,
+    /// Device on the left palm, TTY_DEV is then the one on the right palm. Experiments for one
+    /// hand play every glyph on both.
+    #[arg(long, value_name = "TTY_DEV")]
+    left: Option<PathBuf>,
+    /// Calibrate again before resuming when the device comes back after being disconnected
+    #[arg(long)]
+    recalibrate: bool,
+    /// Strength of every pulse in the trials, from 0 to 255
+    #[arg(long, value_name = "INTENSITY", default_value_t = FULL_INTENSITY)]
+    intensity: u8,
+    /// How long each pulse in the trials lasts, 0 leaves it to the firmware
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_PULSE_MS)]
+    pulse_ms: u16,
+    /// Alphabet to play glyphs from, a built-in one or a .toml file, defaults to the one the
+    /// experiment was designed with
+    #[arg(long, value_name = "ALPHABET")]
+    alphabet: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode bytes captured from the serial line and show the glyphs in them
+    Inspect {
+        /// capture file recorded with --capture, raw binary or hex text (whitespace is ignored)
+        #[arg(value_name = "CAPTURE")]
+        path: PathBuf,
+    },
+    /// Re-send a capture recorded with --capture, keeping its original timing
+    Replay {
+        #[arg(value_name = "CAPTURE")]
+        path: PathBuf,
+        /// serial device to replay to, "auto" to find it or "sim" for the software simulator
+        #[arg(value_name = "TTY_DEV")]
+        tty_path: PathBuf,
+    },
+    /// List serial ports and which of them are tactom devices
+    Devices,
+    /// Measure the timing error the link to the device adds, by sending many glyphs of varying
+    /// length
+    BenchLink {
+        /// serial device to measure, "auto" to find it or "tcp:<host>:<port>" for a bridge
+        #[arg(value_name = "TTY_DEV")]
+        tty_path: PathBuf,
+        /// How many glyphs to send
+        #[arg(long, value_name = "COUNT", default_value_t = 100)]
+        frames: usize,
+        /// Time between the events of each glyph, the alphabet experiment's fast glyphs use 30
+        #[arg(long, value_name = "MS", default_value_t = 30)]
+        step_ms: u16,
+    },
+    /// Play text as one continuous stream, however long, one glyph per character
+    Stream {
+        /// serial device to play on, "auto" to find it or "sim" for the software simulator
+        #[arg(value_name = "TTY_DEV")]
+        tty_path: PathBuf,
+        text: String,
+        /// Alphabet the characters are looked up in, a built-in one or a .toml file
+        #[arg(long, default_value = "roud_graff")]
+        alphabet: String,
+        /// Pause after each character
+        #[arg(long, value_name = "MS", default_value_t = 200)]
+        gap_ms: u64,
+    },
+    /// Write an alphabet out as an alphabet file, or as a C header for the firmware's demo mode
+    ExportAlphabet {
+        /// a built-in alphabet or a .toml file
+        #[arg(value_name = "ALPHABET")]
+        alphabet: String,
+        /// .toml for an alphabet file, .h for a C header
+        #[arg(value_name = "OUTPUT_FILE")]
+        out_path: PathBuf,
+    },
+    /// Play one glyph written in the glyph notation, e.g. "8-4-0 @30" or "col0_up + col1_down"
+    Play {
+        /// serial device to play on, "auto" to find it or "sim" for the software simulator
+        #[arg(value_name = "TTY_DEV")]
+        tty_path: PathBuf,
+        glyph: String,
+        /// Alphabet named glyphs are looked up in, a built-in one or a .toml file
+        #[arg(long, default_value = "distinguish")]
+        alphabet: String,
+    },
 }
 
 #[derive(Serialize)]
@@ -48,6 +172,12 @@ struct DropoutData {
     drop_glyph: String,
     speed: u16,
     drop_played: bool,
+    intensity: u8,
+    pulse_ms: u16,
+    onset1_ms: Option<u64>,
+    offset1_ms: Option<u64>,
+    onset2_ms: Option<u64>,
+    offset2_ms: Option<u64>,
     duration_ms: u128,
     correct: bool,
     unsure: bool,
@@ -57,6 +187,10 @@ struct DropoutData {
 struct DrawData {
     glyph: char,
     speed: u16,
+    intensity: u8,
+    pulse_ms: u16,
+    onset_ms: Option<u64>,
+    offset_ms: Option<u64>,
     duration_ms: u128,
     pathiness: u8,
 }
@@ -65,6 +199,10 @@ struct DrawData {
 struct AlphabetData {
     c: char,
     speed: u16,
+    intensity: u8,
+    pulse_ms: u16,
+    onset_ms: Option<u64>,
+    offset_ms: Option<u64>,
     answer: char,
     duration_ms: u128,
     occurrence: usize,
@@ -72,6 +210,108 @@ struct AlphabetData {
     unsure: bool,
 }
 
+#[derive(Serialize)]
+struct CrossHandData {
+    id: usize,
+    left_glyph: String,
+    right_glyph: String,
+    speed: u16,
+    presentation: &'static str,
+    same_played: bool,
+    intensity: u8,
+    pulse_ms: u16,
+    onset_left_ms: Option<u64>,
+    offset_left_ms: Option<u64>,
+    onset_right_ms: Option<u64>,
+    offset_right_ms: Option<u64>,
+    duration_ms: u128,
+    correct: bool,
+    unsure: bool,
+}
+
+/// What stays the same for every trial of a session
+struct Settings {
+    recalibrate: bool,
+    intensity: u8,
+    pulse_ms: u16,
+    stored: Option<StoredAlphabet>, // The alphabet's templates, if the device stores them
+}
+
+impl Settings {
+    /// A glyph from the alphabet, the way it is played in a trial
+    fn stimulus(&self, glyph: &[Ev], space_ms: u16) -> anyhow::Result<Stimulus> {
+        let events = self.events(glyph, space_ms)?;
+        let stored = match &self.stored {
+            Some(stored) => stored.id(&self.template(glyph)?),
+            None => None,
+        };
+        let scale = TimeScale {
+            num: space_ms,
+            den: 1,
+        };
+        Ok(Stimulus {
+            events,
+            stored: stored.map(|id| (id, scale)),
+        })
+    }
+
+    /// A glyph the way the device stores it, 1 ms apart so that scaling its times by the spacing
+    /// gives the stimulus
+    fn template(&self, glyph: &[Ev]) -> anyhow::Result<Vec<Ev>> {
+        self.events(glyph, 1)
+    }
+
+    fn events(&self, glyph: &[Ev], space_ms: u16) -> anyhow::Result<Vec<Ev>> {
+        let glyph = retime_eq_spaced(glyph, space_ms)
+            .ok_or_else(|| anyhow!("Glyph is too long to play {} ms apart", space_ms))?;
+        let glyph = set_intensity(&glyph, self.intensity);
+        Ok(set_pulse_ms(&glyph, self.pulse_ms))
+    }
+}
+
+/// A glyph the way it is played in a trial, and where the device stores it if it does
+struct Stimulus {
+    events: Vec<Ev>,
+    stored: Option<(u16, TimeScale)>,
+}
+
+impl Stimulus {
+    /// A stored glyph is played by its id, anything else is sent in full
+    fn start(&self, dev: &mut dyn TactileDevice) -> anyhow::Result<()> {
+        match self.stored {
+            Some((id, scale)) => dev.play_stored(id, scale),
+            None => dev.send_glyph(&self.events),
+        }
+    }
+}
+
+/// Where a session's results go. Clones share everything, so that the Ctrl-C handler can close the
+/// output while the experiment is blocked waiting for the participant.
+#[derive(Clone)]
+struct Output {
+    csv: Arc<Mutex<Writer<File>>>,
+    log: SessionLog,
+    trial: Arc<AtomicUsize>, // The trial being run, counting from 1, 0 before the first one
+}
+
+impl Output {
+    /// Appends a row to the CSV, flushed straight away so that nothing is lost if the session ends
+    /// early
+    fn record(&self, row: impl Serialize) -> anyhow::Result<()> {
+        let mut csv = self
+            .csv
+            .lock()
+            .map_err(|_| anyhow!("CSV writer poisoned"))?;
+        csv.serialize(row)?;
+        csv.flush()?;
+        Ok(())
+    }
+
+    fn start_trial(&self, q: usize) {
+        self.trial.store(q + 1, Ordering::Relaxed);
+    }
+}
+
 fn clear_term() {
     print!("\x1b[1;1H\x1b[2J");
 }
@@ -80,10 +320,12 @@ fn flush() {
     io::stdout().flush().unwrap_or(())
 }
 
+/// Given a device, the experimenter can also type "calibrate", or "play" followed by a glyph in
+/// the notation of `notation::parse_glyph` to try it out
 async fn ask<S: AsRef<str>>(
     question: &str,
     possible_answers: &[S],
-    mut calibrate: Option<(&mut TTYPort, &Alphabet)>,
+    mut calibrate: Option<(&mut dyn TactileDevice, &Alphabet)>,
 ) -> anyhow::Result<String> {
     let mut answer = String::new();
     loop {
@@ -91,10 +333,15 @@ async fn ask<S: AsRef<str>>(
         flush();
         stdin().read_line(&mut answer)?;
         let t_answer = answer.trim().to_lowercase();
-        if let Some((tty, a_bet)) = calibrate.as_mut() {
+        if let Some((dev, a_bet)) = calibrate.as_mut() {
             if t_answer == "calibrate" {
                 print!("Now calibrating, press [Enter] to finish:");
-                calibrate_until_enter(tty, a_bet).await?;
+                calibrate_until_enter(*dev, a_bet).await?;
+            } else if let Some(text) = answer.trim().strip_prefix("play ") {
+                // Names are case sensitive, so this goes by what was typed
+                if let Err(e) = play_typed(*dev, a_bet, text).await {
+                    println!("Can't play {}: {}", text, e);
+                }
             }
         }
         for a in possible_answers {
@@ -106,43 +353,137 @@ async fn ask<S: AsRef<str>>(
     }
 }
 
-async fn play_and_wait(tty: &mut TTYPort, glyph: &[Ev]) -> anyhow::Result<()> {
-    queue_events_as_raw(glyph, tty)?;
-    let g_dur = glyph_duration(glyph);
+/// Plays a glyph typed in the notation of `notation::parse_glyph`
+async fn play_typed(
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    text: &str,
+) -> anyhow::Result<()> {
+    let motor_count = block_in_place(|| dev.capabilities())?.motor_count;
+    let glyph = a_bet.parse_glyph(text, motor_count)?;
+    play_glyph(dev, &glyph).await
+}
+
+/// Plays a single glyph and waits for it to finish
+async fn play_glyph(dev: &mut dyn TactileDevice, glyph: &[Ev]) -> anyhow::Result<()> {
+    send_glyph(dev, glyph).await?;
+    wait_played(dev, glyph_duration(glyph)).await?;
+    Ok(())
+}
+
+/// Serial writes may block on the device, so move other tasks off this worker while they happen
+async fn send_glyph(dev: &mut dyn TactileDevice, glyph: &[Ev]) -> anyhow::Result<()> {
+    block_in_place(|| dev.send_glyph(glyph))
+}
+
+/// Plays a stimulus and waits for it to finish, plus a short gap before whatever comes next
+async fn play_and_wait(
+    dev: &mut dyn TactileDevice,
+    stim: &Stimulus,
+) -> anyhow::Result<Option<Playback>> {
+    block_in_place(|| stim.start(dev))?;
+    wait_played(dev, glyph_duration(&stim.events)).await
+}
+
+/// Waits for what was just sent, `g_dur` ms long, to finish, plus a short gap before whatever comes
+/// next
+async fn wait_played(dev: &mut dyn TactileDevice, g_dur: u16) -> anyhow::Result<Option<Playback>> {
+    let timeout = Duration::from_millis(g_dur as u64) + Duration::from_secs_f32(1.0);
+    let playback = block_in_place(|| dev.wait_playback(timeout))?;
+    if playback.is_some() {
+        sleep(Duration::from_secs_f32(1.0)).await;
+        return Ok(playback);
+    }
+    // The device can't tell us when it's done, guess from the glyph length
     if g_dur >= 1000 {
         sleep(Duration::from_secs_f32(1.0) + Duration::from_millis(g_dur as u64)).await;
     } else {
         sleep(Duration::from_secs_f32(2.0)).await;
     }
-    Ok(())
+    while block_in_place(|| dev.status())? == DeviceStatus::Playing {
+        sleep(Duration::from_millis(50)).await;
+    }
+    Ok(None)
 }
 
-async fn calibrate_until_enter(tty: &mut TTYPort, a_bet: &Alphabet) -> anyhow::Result<()> {
+async fn calibrate_until_enter(
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+) -> anyhow::Result<()> {
     let done = Arc::new(RwLock::new(false));
     let done_1 = done.clone();
-    tokio::spawn(async move {
+    // On a blocking thread, so that waiting for Enter doesn't take a worker the Ctrl-C handler
+    // needs
+    spawn_blocking(move || {
         stdin().read_line(&mut String::new()).unwrap_or(0);
-        *done_1.write().await = true;
+        *done_1.blocking_write() = true;
     });
     let mut i: u8 = 0;
     loop {
         let glyph = a_bet.get_other_glyph(&i.to_string());
-        queue_events_as_raw(glyph, tty)?;
+        ignore_reconnect(send_glyph(dev, glyph).await)?;
         sleep(Duration::from_millis(150)).await;
         if *done.read().await {
+            ignore_reconnect(block_in_place(|| dev.stop()))?;
             break;
         }
+        // Walks through the alphabet's numbered glyphs, one for each motor
         i += 1;
-        if i == 12 {
+        if !a_bet.has_other_glyph(&i.to_string()) {
             i = 0;
         }
     }
     Ok(())
 }
 
+/// Calibration only loses the glyph that was playing when the device dropped out, so it just
+/// carries on once the device is back
+fn ignore_reconnect(result: anyhow::Result<()>) -> anyhow::Result<()> {
+    match result {
+        Err(e) if e.is::<Reconnected>() => Ok(()),
+        result => result,
+    }
+}
+
+/// Gets the participant going again after the device came back, the interrupted problem is then
+/// retried without asking. The device may have lost power, so the alphabet is stored again, for as
+/// many times as it drops out while that happens.
+async fn after_reconnect(
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    println!("\nThe device was disconnected and is back, restarting this problem.");
+    if settings.recalibrate {
+        print!("Calibrating, press [Enter] to continue:");
+        flush();
+        calibrate_until_enter(dev, a_bet).await?;
+    } else {
+        sleep(Duration::from_secs_f32(1.0)).await;
+    }
+    // After calibrating, which carries on through drop outs, so that the device playing the next
+    // problem has the alphabet
+    if let Some(stored) = &settings.stored {
+        loop {
+            let uploaded = block_in_place(|| {
+                // A reopened device hasn't been asked what it can do yet
+                dev.capabilities()?;
+                stored.upload(dev)
+            });
+            match uploaded {
+                Err(e) if e.is::<Reconnected>() => {
+                    println!("The device was disconnected again, storing the alphabet again.")
+                }
+                result => break result?,
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn rest(
     rest_timer: Instant,
-    tty: &mut TTYPort,
+    dev: &mut dyn TactileDevice,
     a_bet: &Alphabet,
     q_info: Option<(usize, usize)>,
 ) -> anyhow::Result<Instant> {
@@ -160,7 +501,7 @@ When you are ready, please press [Enter] to calibrate:"
         stdin().read_line(&mut String::new())?;
         print!("\nCalibrating, press [Enter] to continue:");
         flush();
-        calibrate_until_enter(tty, a_bet).await?;
+        calibrate_until_enter(dev, a_bet).await?;
         Ok(Instant::now())
     } else {
         Ok(rest_timer)
@@ -168,12 +509,13 @@ When you are ready, please press [Enter] to calibrate:"
 }
 
 async fn dropout_problem(
-    tty: &mut TTYPort,
+    dev: &mut dyn TactileDevice,
     a_bet: &Alphabet,
     prob: (&str, &str, u16),
     q: usize,
     q_len: usize,
     prob_id: usize,
+    settings: &Settings,
 ) -> anyhow::Result<DropoutData> {
     let play_dropout: bool = random();
     let swap_glyphs: bool = random();
@@ -189,23 +531,23 @@ async fn dropout_problem(
     sleep(Duration::from_secs_f32(1.0)).await;
     println!("Glyph 1...");
     flush();
-    play_and_wait(
-        tty,
-        &retime_eq_spaced(a_bet.get_other_glyph(glyph1), prob.2),
+    let playback1 = play_and_wait(
+        dev,
+        &settings.stimulus(a_bet.get_other_glyph(glyph1), prob.2)?,
     )
     .await?;
     println!("Glyph 2...");
     flush();
-    play_and_wait(
-        tty,
-        &retime_eq_spaced(a_bet.get_other_glyph(glyph2), prob.2),
+    let playback2 = play_and_wait(
+        dev,
+        &settings.stimulus(a_bet.get_other_glyph(glyph2), prob.2)?,
     )
     .await?;
     let start = Instant::now();
     let answer = ask(
         "Did the device play the same pattern twice?\n(type 'y','n' or '?' if you're unsure, then [Enter]): ",
         &["y", "n", "?"],
-        Some((tty, a_bet)),
+        Some((dev, a_bet)),
     ).await?;
     let duration = Instant::now().duration_since(start);
     let unsure = answer == "?";
@@ -216,29 +558,21 @@ async fn dropout_problem(
         drop_glyph: prob.1.to_owned(),
         speed: prob.2,
         drop_played: play_dropout,
+        intensity: settings.intensity,
+        pulse_ms: settings.pulse_ms,
+        onset1_ms: playback1.map(|p| p.onset_ms),
+        offset1_ms: playback1.map(|p| p.offset_ms),
+        onset2_ms: playback2.map(|p| p.onset_ms),
+        offset2_ms: playback2.map(|p| p.offset_ms),
         duration_ms: duration.as_millis(),
         correct,
         unsure,
     })
 }
 
-async fn dropout_exp(
-    mut out_writer: Writer<File>,
-    mut tty: TTYPort,
-    a_bet: &Alphabet,
-) -> anyhow::Result<()> {
-    clear_term();
-    println!(
-        "In this experiment, two patterns will be played one after the other on the device.
-
-You will then be asked if these two patterns were identical or subtly different.
-
-Press [Enter] when you're ready to begin:"
-    );
-    flush();
-    calibrate_until_enter(&mut tty, a_bet).await?;
-
-    let prob_pairs = vec![
+/// Glyphs of the distinguish alphabet, each paired with a version that misses a pulse
+fn dropout_pairs() -> Vec<(&'static str, &'static str)> {
+    vec![
         ("col0_up", "col0_up_dropout0"),
         ("col0_up", "col0_up_dropout1"),
         ("col2_down", "col2_down_dropout0"),
@@ -268,7 +602,27 @@ Press [Enter] when you're ready to begin:"
         ("anticlockwise", "anticlockwise_dropout2"),
         ("anticlockwise", "anticlockwise_dropout3"),
         ("anticlockwise", "anticlockwise_dropout4"),
-    ];
+    ]
+}
+
+async fn dropout_exp(
+    out: &Output,
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    clear_term();
+    println!(
+        "In this experiment, two patterns will be played one after the other on the device.
+
+You will then be asked if these two patterns were identical or subtly different.
+
+Press [Enter] when you're ready to begin:"
+    );
+    flush();
+    calibrate_until_enter(dev, a_bet).await?;
+
+    let prob_pairs = dropout_pairs();
     let prob_len = prob_pairs.len();
     let speeds = iter::repeat(50)
         .take(prob_len)
@@ -284,15 +638,16 @@ Press [Enter] when you're ready to begin:"
     let q_len = problems.len();
     let mut rest_timer = Instant::now();
     for (q, (p_id, prob)) in problems.into_iter().enumerate() {
-        rest_timer = rest(rest_timer, &mut tty, a_bet, Some((q, q_len))).await?;
+        out.start_trial(q);
+        rest_timer = rest(rest_timer, dev, a_bet, Some((q, q_len))).await?;
         loop {
             clear_term();
-            match dropout_problem(&mut tty, a_bet, prob, q, q_len, p_id).await {
+            match dropout_problem(dev, a_bet, prob, q, q_len, p_id, settings).await {
                 Ok(data) => {
-                    out_writer.serialize(data)?;
-                    out_writer.flush()?;
+                    out.record(data)?;
                     break;
                 }
+                Err(e) if e.is::<Reconnected>() => after_reconnect(dev, a_bet, settings).await?,
                 Err(e) => {
                     println!("An error has occured on problem {}, {}", p_id, e);
                     let answer = ask(
@@ -302,17 +657,195 @@ Press [Enter] when you're ready to begin:"
                     )
                     .await?;
                     if answer == "n" {
-                        out_writer.serialize(DropoutData {
+                        out.record(DropoutData {
                             id: p_id,
                             glyph: "error".to_owned(),
                             drop_glyph: "error".to_owned(),
                             speed: 0,
                             drop_played: false,
+                            intensity: 0,
+                            pulse_ms: 0,
+                            onset1_ms: None,
+                            offset1_ms: None,
+                            onset2_ms: None,
+                            offset2_ms: None,
+                            duration_ms: 0,
+                            correct: false,
+                            unsure: false,
+                        })?;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How a cross-hand trial presents its two glyphs
+#[derive(Clone, Copy)]
+enum Presentation {
+    OneAfterOther(Hand), // Starting on this hand
+    Together,
+}
+
+impl Presentation {
+    fn name(&self) -> &'static str {
+        match self {
+            Presentation::OneAfterOther(Hand::Left) => "left_first",
+            Presentation::OneAfterOther(Hand::Right) => "right_first",
+            Presentation::Together => "together",
+        }
+    }
+}
+
+/// Two glyphs that differ by a pulse, the speed to play them at and how to present them
+type CrossHandProblem = (&'static str, &'static str, u16, Presentation);
+
+async fn cross_hand_problem(
+    pair: &mut DevicePair,
+    a_bet: &Alphabet,
+    prob: CrossHandProblem,
+    q: usize,
+    q_len: usize,
+    prob_id: usize,
+    settings: &Settings,
+) -> anyhow::Result<CrossHandData> {
+    let play_same: bool = random();
+    let swap_glyphs: bool = random();
+    let (glyph1, glyph2) = if play_same {
+        (prob.0, prob.0)
+    } else if swap_glyphs {
+        (prob.1, prob.0)
+    } else {
+        (prob.0, prob.1)
+    };
+    let stim1 = settings.stimulus(a_bet.get_other_glyph(glyph1), prob.2)?;
+    let stim2 = settings.stimulus(a_bet.get_other_glyph(glyph2), prob.2)?;
+    println!("----- Question: {}/{} -----", q + 1, q_len);
+    flush();
+    sleep(Duration::from_secs_f32(1.0)).await;
+    // glyph1 goes on the hand that starts, or the left one when both play together
+    let (left_glyph, right_glyph, [left, right]) = match prob.3 {
+        Presentation::Together => {
+            println!("Both hands...");
+            flush();
+            let start1 = |dev: &mut dyn TactileDevice| stim1.start(dev);
+            let start2 = |dev: &mut dyn TactileDevice| stim2.start(dev);
+            block_in_place(|| pair.play(Some(start1), Some(start2)))?;
+            let g_dur = glyph_duration(&stim1.events).max(glyph_duration(&stim2.events));
+            wait_played(pair, g_dur).await?;
+            (glyph1, glyph2, pair.playbacks())
+        }
+        Presentation::OneAfterOther(first) => {
+            let second = first.other();
+            println!("Glyph 1, {} hand...", first);
+            flush();
+            let playback1 = play_and_wait(pair.hand(first), &stim1).await?;
+            println!("Glyph 2, {} hand...", second);
+            flush();
+            let playback2 = play_and_wait(pair.hand(second), &stim2).await?;
+            match first {
+                Hand::Left => (glyph1, glyph2, [playback1, playback2]),
+                Hand::Right => (glyph2, glyph1, [playback2, playback1]),
+            }
+        }
+    };
+    let start = Instant::now();
+    let answer = ask(
+        "Did your two hands feel the same pattern?\n(type 'y','n' or '?' if you're unsure, then [Enter]): ",
+        &["y", "n", "?"],
+        Some((pair, a_bet)),
+    ).await?;
+    let duration = Instant::now().duration_since(start);
+    let unsure = answer == "?";
+    let correct = ((answer == "y") == play_same) && !unsure;
+    Ok(CrossHandData {
+        id: prob_id,
+        left_glyph: left_glyph.to_owned(),
+        right_glyph: right_glyph.to_owned(),
+        speed: prob.2,
+        presentation: prob.3.name(),
+        same_played: play_same,
+        intensity: settings.intensity,
+        pulse_ms: settings.pulse_ms,
+        onset_left_ms: left.map(|p| p.onset_ms),
+        offset_left_ms: left.map(|p| p.offset_ms),
+        onset_right_ms: right.map(|p| p.onset_ms),
+        offset_right_ms: right.map(|p| p.offset_ms),
+        duration_ms: duration.as_millis(),
+        correct,
+        unsure,
+    })
+}
+
+async fn cross_hand_exp(
+    out: &Output,
+    pair: &mut DevicePair,
+    a_bet: &Alphabet,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    clear_term();
+    println!(
+        "In this experiment, you will wear a device on each hand. Two patterns will be played, either one hand after the other or on both hands at once.
+
+You will then be asked if your two hands felt identical or subtly different patterns.
+
+Press [Enter] when you're ready to begin:"
+    );
+    flush();
+    calibrate_until_enter(pair, a_bet).await?;
+
+    let presentations = [
+        Presentation::OneAfterOther(Hand::Left),
+        Presentation::OneAfterOther(Hand::Right),
+        Presentation::Together,
+    ];
+    let mut problems: Vec<(usize, CrossHandProblem)> = dropout_pairs()
+        .into_iter()
+        .flat_map(|(p1, p2)| presentations.map(|p| (p1, p2, 150, p)))
+        .enumerate()
+        .collect();
+    problems.shuffle(&mut rng());
+    let q_len = problems.len();
+    let mut rest_timer = Instant::now();
+    for (q, (p_id, prob)) in problems.into_iter().enumerate() {
+        out.start_trial(q);
+        rest_timer = rest(rest_timer, pair, a_bet, Some((q, q_len))).await?;
+        loop {
+            clear_term();
+            match cross_hand_problem(pair, a_bet, prob, q, q_len, p_id, settings).await {
+                Ok(data) => {
+                    out.record(data)?;
+                    break;
+                }
+                Err(e) if e.is::<Reconnected>() => after_reconnect(pair, a_bet, settings).await?,
+                Err(e) => {
+                    println!("An error has occured on problem {}, {}", p_id, e);
+                    let answer = ask(
+                        "Would you like to retry this problem (otherwise, skip it)?[Y/n]: ",
+                        &["y", "n", ""],
+                        None,
+                    )
+                    .await?;
+                    if answer == "n" {
+                        out.record(CrossHandData {
+                            id: p_id,
+                            left_glyph: "error".to_owned(),
+                            right_glyph: "error".to_owned(),
+                            speed: 0,
+                            presentation: prob.3.name(),
+                            same_played: false,
+                            intensity: 0,
+                            pulse_ms: 0,
+                            onset_left_ms: None,
+                            offset_left_ms: None,
+                            onset_right_ms: None,
+                            offset_right_ms: None,
                             duration_ms: 0,
                             correct: false,
                             unsure: false,
                         })?;
-                        out_writer.flush()?;
                         break;
                     }
                 }
@@ -323,19 +856,20 @@ Press [Enter] when you're ready to begin:"
 }
 
 async fn alphabet_problem(
-    tty: &mut TTYPort,
+    dev: &mut dyn TactileDevice,
     a_bet: &Alphabet,
     prob: (char, u16),
     q: usize,
     q_len: usize,
     occurrence: usize,
+    settings: &Settings,
 ) -> anyhow::Result<AlphabetData> {
     println!("----- Question: {}/{} -----", q + 1, q_len);
     flush();
     sleep(Duration::from_secs_f32(1.0)).await;
     println!("Playing glyph...");
     flush();
-    play_and_wait(tty, &retime_eq_spaced(a_bet.get_glyph(prob.0), prob.1)).await?;
+    let playback = play_and_wait(dev, &settings.stimulus(a_bet.get_glyph(prob.0), prob.1)?).await?;
     let start = Instant::now();
     let options: Vec<String> = ('a'..='z')
         .chain(iter::once('?'))
@@ -344,7 +878,7 @@ async fn alphabet_problem(
     let answer = ask(
         "What letter just played?\n(type 'a', 'b', 'c', ..., 'z' or '?' if you're unsure, then [Enter]): ",
         &options,
-        Some((tty, a_bet)),
+        Some((dev, a_bet)),
     ).await?;
     let duration = Instant::now().duration_since(start);
     let answer = answer.chars().nth(0).unwrap();
@@ -361,6 +895,10 @@ async fn alphabet_problem(
     Ok(AlphabetData {
         c: prob.0,
         speed: prob.1,
+        intensity: settings.intensity,
+        pulse_ms: settings.pulse_ms,
+        onset_ms: playback.map(|p| p.onset_ms),
+        offset_ms: playback.map(|p| p.offset_ms),
         answer,
         duration_ms: duration.as_millis(),
         occurrence,
@@ -369,10 +907,27 @@ async fn alphabet_problem(
     })
 }
 
+async fn learn_glyph(
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    c: char,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    println!("Playing...");
+    flush();
+    play_and_wait(dev, &settings.stimulus(a_bet.get_glyph(c), 150)?).await?;
+    println!("Playing fast...");
+    flush();
+    play_and_wait(dev, &settings.stimulus(a_bet.get_glyph(c), 30)?).await?;
+    Ok(())
+}
+
 async fn alphabet_exp(
-    mut out_writer: Writer<File>,
-    mut tty: TTYPort,
+    out: &Output,
+    dev: &mut dyn TactileDevice,
     a_bet: &Alphabet,
+    profile: &Profile,
+    settings: &Settings,
 ) -> anyhow::Result<()> {
     clear_term();
     print!(
@@ -382,28 +937,29 @@ You will be shown what all of the glyphs are before you begin answering question
 Press [Enter] when you're ready to begin:"
     );
     flush();
-    calibrate_until_enter(&mut tty, a_bet).await?;
+    calibrate_until_enter(dev, a_bet).await?;
 
     let mut rest_timer = Instant::now();
     'learn: for c in 'a'..='z' {
-        rest_timer = rest(rest_timer, &mut tty, a_bet, None).await?;
+        rest_timer = rest(rest_timer, dev, a_bet, None).await?;
         clear_term();
         println!("----- Glyph '{}' -----", c);
-        println_glyph(a_bet.get_glyph(c));
+        println_glyph(profile, a_bet.get_glyph(c));
         flush();
         sleep(Duration::from_secs_f32(1.0)).await;
         let mut answer = String::new();
         while answer != "n" {
-            println!("Playing...");
-            flush();
-            play_and_wait(&mut tty, &retime_eq_spaced(a_bet.get_glyph(c), 150)).await?;
-            println!("Playing fast...");
-            flush();
-            play_and_wait(&mut tty, &retime_eq_spaced(a_bet.get_glyph(c), 30)).await?;
+            if let Err(e) = learn_glyph(dev, a_bet, c, settings).await {
+                if !e.is::<Reconnected>() {
+                    return Err(e);
+                }
+                after_reconnect(dev, a_bet, settings).await?;
+                continue;
+            }
             answer = ask(
                 "Would you like to replay this glyph (otherwise, advance to the next letter)?[Y/n]: ",
                 &["y", "n", "skip", ""],
-                Some((&mut tty, a_bet)),
+                Some((&mut *dev, a_bet)),
             ).await?;
             if answer == "skip" {
                 break 'learn;
@@ -423,24 +979,26 @@ Press [Enter] when you're ready to begin:"
     let q_len = problems.len();
     let mut occurrences = vec![0; 'z' as usize - 'a' as usize + 1];
     for (q, prob) in problems.into_iter().enumerate() {
+        out.start_trial(q);
         loop {
             clear_term();
             match alphabet_problem(
-                &mut tty,
+                dev,
                 a_bet,
                 prob,
                 q,
                 q_len,
                 occurrences[prob.0 as usize - 'a' as usize],
+                settings,
             )
             .await
             {
                 Ok(data) => {
                     occurrences[prob.0 as usize - 'a' as usize] += 1;
-                    out_writer.serialize(data)?;
-                    out_writer.flush()?;
+                    out.record(data)?;
                     break;
                 }
+                Err(e) if e.is::<Reconnected>() => after_reconnect(dev, a_bet, settings).await?,
                 Err(e) => {
                     println!("An error has occured on problem {}, {}", prob.0, e);
                     let answer = ask(
@@ -450,16 +1008,19 @@ Press [Enter] when you're ready to begin:"
                     )
                     .await?;
                     if answer == "n" {
-                        out_writer.serialize(AlphabetData {
+                        out.record(AlphabetData {
                             c: '%',
                             speed: 0,
+                            intensity: 0,
+                            pulse_ms: 0,
+                            onset_ms: None,
+                            offset_ms: None,
                             answer: ' ',
                             duration_ms: 0,
                             occurrence: 0,
                             correct: false,
                             unsure: false,
                         })?;
-                        out_writer.flush()?;
                         break;
                     }
                 }
@@ -470,37 +1031,43 @@ Press [Enter] when you're ready to begin:"
 }
 
 async fn draw_problem(
-    tty: &mut TTYPort,
+    dev: &mut dyn TactileDevice,
     a_bet: &Alphabet,
     prob: (char, u16),
     q: usize,
     q_len: usize,
+    settings: &Settings,
 ) -> anyhow::Result<DrawData> {
     println!("----- Question: {}/{} -----", q + 1, q_len);
     flush();
     sleep(Duration::from_secs_f32(1.0)).await;
     println!("Playing glyph...");
     flush();
-    play_and_wait(tty, &retime_eq_spaced(a_bet.get_glyph(prob.0), prob.1)).await?;
+    let playback = play_and_wait(dev, &settings.stimulus(a_bet.get_glyph(prob.0), prob.1)?).await?;
     let start = Instant::now();
     let answer = ask(
         "Please draw the glyph you just felt, then rate how \"pathy\" felt it was from 1 to 5.\n(type '1', '2', '3', '4' or '5', then [Enter]): ",
         &["1", "2", "3", "4", "5"],
-        Some((tty, a_bet)),
+        Some((dev, a_bet)),
     ).await?;
     let duration = Instant::now().duration_since(start);
     Ok(DrawData {
         glyph: prob.0,
         speed: prob.1,
+        intensity: settings.intensity,
+        pulse_ms: settings.pulse_ms,
+        onset_ms: playback.map(|p| p.onset_ms),
+        offset_ms: playback.map(|p| p.offset_ms),
         duration_ms: duration.as_millis(),
         pathiness: answer.parse()?,
     })
 }
 
 async fn draw_exp(
-    mut out_writer: Writer<File>,
-    mut tty: TTYPort,
+    out: &Output,
+    dev: &mut dyn TactileDevice,
     a_bet: &Alphabet,
+    settings: &Settings,
 ) -> anyhow::Result<()> {
     clear_term();
     print!(
@@ -511,7 +1078,7 @@ You will be asked to draw this path with a pencil and paper, and type a rating o
 Press [Enter] when you're ready to begin:"
     );
     flush();
-    calibrate_until_enter(&mut tty, a_bet).await?;
+    calibrate_until_enter(dev, a_bet).await?;
 
     let abet_len = 'z' as usize - 'a' as usize + 1;
     let speeds = iter::repeat(50)
@@ -526,15 +1093,16 @@ Press [Enter] when you're ready to begin:"
     let q_len = problems.len();
     let mut rest_timer = Instant::now();
     for (q, prob) in problems.into_iter().enumerate() {
-        rest_timer = rest(rest_timer, &mut tty, a_bet, Some((q, q_len))).await?;
+        out.start_trial(q);
+        rest_timer = rest(rest_timer, dev, a_bet, Some((q, q_len))).await?;
         loop {
             clear_term();
-            match draw_problem(&mut tty, a_bet, prob, q, q_len).await {
+            match draw_problem(dev, a_bet, prob, q, q_len, settings).await {
                 Ok(data) => {
-                    out_writer.serialize(data)?;
-                    out_writer.flush()?;
+                    out.record(data)?;
                     break;
                 }
+                Err(e) if e.is::<Reconnected>() => after_reconnect(dev, a_bet, settings).await?,
                 Err(e) => {
                     println!("An error has occured on problem {}, {}", q, e);
                     let answer = ask(
@@ -544,13 +1112,16 @@ Press [Enter] when you're ready to begin:"
                     )
                     .await?;
                     if answer == "n" {
-                        out_writer.serialize(DrawData {
+                        out.record(DrawData {
                             glyph: '?',
                             speed: 0,
+                            intensity: 0,
+                            pulse_ms: 0,
+                            onset_ms: None,
+                            offset_ms: None,
                             duration_ms: 0,
                             pathiness: 0,
                         })?;
-                        out_writer.flush()?;
                         break;
                     }
                 }
@@ -561,24 +1132,603 @@ Press [Enter] when you're ready to begin:"
     Ok(())
 }
 
-#[tokio::main(flavor = "multi_thread")]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+/// Name of the built-in alphabet an experiment plays glyphs from
+fn exp_alphabet(exp: Exp) -> &'static str {
+    match exp {
+        Exp::Dropout | Exp::CrossHand => "distinguish",
+        Exp::Alphabet | Exp::Draw => "roud_graff",
+    }
+}
+
+/// Glyphs an experiment looks up by name, calibration's one for each motor included. An alphabet
+/// given with --alphabet has to have all of them.
+fn exp_glyph_names(exp: Exp, motor_count: u8) -> Vec<String> {
+    let mut names: Vec<String> = (0..motor_count).map(|i| i.to_string()).collect();
+    if matches!(exp, Exp::Dropout | Exp::CrossHand) {
+        let pairs = dropout_pairs().into_iter();
+        names.extend(pairs.flat_map(|(a, b)| [a.to_owned(), b.to_owned()]));
+    }
+    names
+}
+
+const NEEDS_TWO_HANDS: &str = "The cross-hand experiment needs a device on each palm, pass --left";
+
+async fn run_exp(
+    exp: Exp,
+    out: &Output,
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    profile: &Profile,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    match exp {
+        Exp::Dropout => dropout_exp(out, dev, a_bet, settings).await,
+        Exp::Alphabet => alphabet_exp(out, dev, a_bet, profile, settings).await,
+        Exp::Draw => draw_exp(out, dev, a_bet, settings).await,
+        Exp::CrossHand => Err(anyhow!(NEEDS_TWO_HANDS)),
+    }
+}
+
+fn inspect(path: &Path, profile: &Profile) -> anyhow::Result<()> {
+    let bytes = load_bytes(path)?;
+    let frames = decode_glyphs(&bytes, profile.motor_count());
+    let mut stored = HashMap::new();
+    let mut errors = 0;
+    for (i, (offset, frame)) in frames.iter().enumerate() {
+        let print_glyph = |what: &str, glyph: &[Ev]| {
+            println!(
+                "----- Frame {} @ byte {}: {}, {} events, {} ms -----",
+                i,
+                offset,
+                what,
+                glyph.len(),
+                glyph_duration(glyph)
+            );
+            for ev in glyph {
+                println!("  {:>5} ms -> {}", ev.ms_time, ev);
+            }
+            println_glyph(profile, glyph);
+        };
+        let print_command =
+            |what: &str| println!("----- Frame {} @ byte {}: {} -----", i, offset, what);
+        match frame {
+            Ok(Frame::Glyph(glyph)) => print_glyph("glyph", glyph),
+            Ok(Frame::QueueGlyph(glyph)) => print_glyph("queued glyph", glyph),
+            Ok(Frame::StoreGlyph { id, glyph }) => {
+                print_glyph(&format!("stores glyph {}", id), glyph);
+                stored.insert(*id, glyph.clone());
+            }
+            Ok(Frame::PlayStored { id, scale }) => {
+                let what = format!(
+                    "plays stored glyph {}, times scaled by {}/{}",
+                    id, scale.num, scale.den
+                );
+                // Shown as it plays when the capture stored it earlier
+                match stored.get(id).and_then(|glyph| scale.scale_glyph(glyph)) {
+                    Some(glyph) => print_glyph(&what, &glyph),
+                    None => print_command(&what),
+                }
+            }
+            Ok(Frame::Stop) => print_command("stop"),
+            Ok(Frame::ClearStore) => {
+                print_command("clears the stored glyphs");
+                stored.clear();
+            }
+            Ok(Frame::Query { kind }) => {
+                print_command(&format!("query, packet kind {:#04x}", kind))
+            }
+            Err(e) => {
+                errors += 1;
+                print_command(&format!("error: {}", e));
+            }
+        }
+    }
+    println!(
+        "{} bytes, {} frames, {} errors",
+        bytes.len(),
+        frames.len(),
+        errors
+    );
+    Ok(())
+}
+
+fn open_serial(tty_path: &Path, profile: &Profile) -> anyhow::Result<SerialDevice> {
+    if tty_path == Path::new("auto") {
+        let (path, dev) = find_device(profile.serial.baud_rate)?;
+        println!("Found tactom device on {}", path.display());
+        Ok(dev)
+    } else {
+        SerialDevice::open(tty_path, profile.serial.baud_rate)
+    }
+}
+
+fn list_devices(profile: &Profile) -> anyhow::Result<()> {
+    let candidates = list_candidates(profile.serial.baud_rate)?;
+    if candidates.is_empty() {
+        println!("No serial ports found");
+    }
+    for c in candidates {
+        let usb = match &c.usb {
+            Some(usb) => format!(
+                "{:04x}:{:04x} {}",
+                usb.vid,
+                usb.pid,
+                usb.product.as_deref().unwrap_or("")
+            ),
+            None => "not USB".to_owned(),
+        };
+        let status = match (&c.identity, c.is_tactom(), c.matches_usb_id()) {
+            (Some(id), true, _) => format!("tactom device ({})", id),
+            (Some(id), false, _) => format!("not a tactom device ({})", id),
+            (None, _, true) => "no answer to identify".to_owned(),
+            (None, _, false) => "unknown USB id, not probed".to_owned(),
+        };
+        println!("{:<24} {:<36} {}", c.path.display(), usb, status);
+    }
+    Ok(())
+}
 
-    if Path::exists(&cli.out_path) && cli.out_path != PathBuf::from("/dev/null") {
+fn print_samples(name: &str, samples: &Samples) {
+    let ms = |p: f64| samples.percentile(p).unwrap_or(f64::NAN);
+    println!(
+        "{:<24} {:>6} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2}",
+        name,
+        samples.len(),
+        ms(50.0),
+        ms(90.0),
+        ms(99.0),
+        ms(100.0),
+        samples.std_dev().unwrap_or(f64::NAN)
+    );
+}
+
+fn bench_link(
+    tty_path: &Path,
+    frames: usize,
+    step_ms: u16,
+    profile: &Profile,
+) -> anyhow::Result<()> {
+    if tty_path == Path::new("sim") {
+        return Err(anyhow!(
+            "bench-link measures a serial link, run tactom-virtual to try it without hardware"
+        ));
+    }
+    let mut dev = open_serial(tty_path, profile)?;
+    let caps = dev.capabilities()?;
+    println!("Firmware: {}", caps);
+    println!(
+        "Sending {} glyphs with events {} ms apart...",
+        frames, step_ms
+    );
+    let report = bench::bench_link(&mut dev, &caps, frames, step_ms)?;
+
+    println!(
+        "\n{:<24} {:>6} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "ms", "n", "p50", "p90", "p99", "max", "std dev"
+    );
+    for (len, samples) in &report.writes {
+        print_samples(&format!("write, {} byte frame", len), samples);
+    }
+    match &report.echo {
+        Some(echo) => print_samples("echo round trip", echo),
+        None => println!("echo round trip          firmware can't echo"),
+    }
+    match (&report.onset, &report.duration_error) {
+        (Some(onset), Some(error)) => {
+            print_samples("onset latency", onset);
+            print_samples("duration error", error);
+        }
+        _ => println!("onset latency            firmware doesn't report playback"),
+    }
+    if report.echoes_lost > 0 {
+        println!("{} echoes were lost", report.echoes_lost);
+    }
+    Ok(())
+}
+
+fn stream_text(dev: &mut dyn TactileDevice, timeline: &Timeline) -> anyhow::Result<()> {
+    let caps = dev.capabilities()?;
+    println!("Firmware: {}", caps);
+    println!(
+        "Streaming {:.1} s in glyphs of up to {} bytes...",
+        timeline.duration_ms() as f64 / 1000.0,
+        caps.max_glyph_len
+    );
+    let played = stream::stream(dev, timeline, &caps)?;
+    let first_onset = played.first().and_then(|chunk| chunk.onset_ms);
+    for (i, chunk) in played.iter().enumerate() {
+        let planned = chunk.planned_ms as f64 / 1000.0;
+        match (chunk.onset_ms, first_onset) {
+            (Some(onset), Some(first)) => {
+                let drift = onset as i64 - first as i64 - chunk.planned_ms as i64;
+                println!("chunk {:>3} at {:>8.3} s, {:+} ms off", i, planned, drift);
+            }
+            _ => println!(
+                "chunk {:>3} at {:>8.3} s, firmware doesn't report playback",
+                i, planned
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Glyphs drawn as paths are written out as the motors they pass on the board of `profile`
+fn export_alphabet(alphabet: &str, out_path: &Path, profile: &Profile) -> anyhow::Result<()> {
+    if Path::exists(out_path) {
         return Err(anyhow!("OUTPUT_FILE path already exists"));
     }
+    let a_bet = Alphabet::find(alphabet, profile)?;
+    let text = match out_path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => a_bet.to_toml(),
+        Some("h") => a_bet.to_c_header(),
+        _ => return Err(anyhow!("OUTPUT_FILE has to end in .toml or .h")),
+    };
+    fs::write(out_path, text)?;
+    println!(
+        "Wrote the {} alphabet to {}",
+        a_bet.name,
+        out_path.display()
+    );
+    Ok(())
+}
 
-    let tty = TTYPort::open(&serialport::new(cli.tty_path.to_string_lossy(), 115200))?;
-    let out_writer = csv::WriterBuilder::new().from_path(cli.out_path)?;
+async fn replay(path: &Path, dev: &mut dyn TactileDevice) -> anyhow::Result<()> {
+    let writes = read_capture(path)?;
+    let start = Instant::now();
+    for (i, (at, bytes)) in writes.iter().enumerate() {
+        sleep(at.saturating_sub(start.elapsed())).await;
+        block_in_place(|| dev.send_raw(bytes))?;
+        println!(
+            "[{:>8} ms] sent write {}/{}",
+            at.as_millis(),
+            i + 1,
+            writes.len()
+        );
+    }
+    // Let the last glyph play out before the device is closed
+    sleep(Duration::from_secs_f32(1.0)).await;
+    while block_in_place(|| dev.status())? == DeviceStatus::Playing {
+        sleep(Duration::from_millis(50)).await;
+    }
+    Ok(())
+}
 
-    let alphabets = init_alphabets();
+fn print_sim_summary(dev: &SimDevice) {
+    let firmware = dev.firmware();
+    eprintln!(
+        "Simulator played {} glyphs ({} motor firings, {} frames dropped)",
+        firmware.glyph_count(),
+        firmware.timeline().len(),
+        firmware.dropped_frames()
+    );
+}
 
-    match cli.exp {
-        Exp::Dropout => dropout_exp(out_writer, tty, alphabets.get("distinguish").unwrap()).await,
-        Exp::Alphabet => alphabet_exp(out_writer, tty, alphabets.get("roud_graff").unwrap()).await,
-        Exp::Draw => draw_exp(out_writer, tty, alphabets.get("roud_graff").unwrap()).await,
-    }?;
+/// On Ctrl-C, silences the device, closes the output and exits. Runs as its own task because the
+/// experiment spends most of its time blocked reading stdin.
+fn handle_ctrl_c(stop: Option<StopHandle>, out: Output) {
+    tokio::spawn(async move {
+        if let Err(e) = ctrl_c().await {
+            eprintln!(
+                "Can't catch Ctrl-C, it will leave the device playing: {}",
+                e
+            );
+            return;
+        }
+        if let Some(stop) = stop {
+            if let Err(e) = stop.stop() {
+                eprintln!("Could not stop the device: {}", e);
+            }
+        }
+        if let Ok(mut csv) = out.csv.lock() {
+            csv.flush().unwrap_or(());
+        }
+        let msg = match out.trial.load(Ordering::Relaxed) {
+            0 => "Aborted before the first trial".to_owned(),
+            trial => format!("Aborted at trial {}", trial),
+        };
+        out.log.log(&msg).unwrap_or(());
+        println!("\n{}", msg);
+        // 128 + SIGINT, like a shell reports a process killed by Ctrl-C
+        process::exit(130);
+    });
+}
 
+/// Asks the device what it can do before anything is played. Refuses to run when it can't play the
+/// alphabet, and falls back to full intensity and default pulses when it can't vary them. What was
+/// agreed on goes in the session log.
+fn negotiate(
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    profile: &Profile,
+    settings: &mut Settings,
+    log: &SessionLog,
+) -> anyhow::Result<()> {
+    let caps = block_in_place(|| dev.capabilities())?;
+    log.log(&format!("Firmware capabilities: {}", caps))?;
+    if caps.protocol_version != PROTOCOL_VERSION {
+        return Err(anyhow!(
+            "The device speaks protocol version {}, this program only speaks version {}",
+            caps.protocol_version,
+            PROTOCOL_VERSION
+        ));
+    }
+    if caps.motor_count != profile.motor_count() {
+        return Err(anyhow!(
+            "The device has {} motors but the {} profile has {}, pass the board's profile with --profile",
+            caps.motor_count,
+            profile.name,
+            profile.motor_count()
+        ));
+    }
+    a_bet
+        .check_with(|glyph| caps.check_glyph(glyph))
+        .map_err(|(glyph, e)| {
+            anyhow!(
+                "The device can't play glyph {} of the {} alphabet: {}",
+                glyph,
+                a_bet.name,
+                e
+            )
+        })?;
+    let downgrade = |what: &str| -> anyhow::Result<()> {
+        let msg = format!(
+            "The firmware can't vary {}, using its default instead",
+            what
+        );
+        println!("{}", msg);
+        log.log(&msg)
+    };
+    if settings.intensity != FULL_INTENSITY && !caps.has(FEATURE_INTENSITY) {
+        downgrade("intensity")?;
+        settings.intensity = FULL_INTENSITY;
+    }
+    if settings.pulse_ms != DEFAULT_PULSE_MS && !caps.has(FEATURE_PULSE_MS) {
+        downgrade("pulse length")?;
+        settings.pulse_ms = DEFAULT_PULSE_MS;
+    }
+    if caps.has(FEATURE_GLYPH_STORE) {
+        // Stored after the downgrades, the templates carry the intensity and pulse length
+        match a_bet.upload(dev, |glyph| settings.template(glyph)) {
+            Ok(stored) => {
+                log.log(&format!(
+                    "Stored the alphabet on the device, {}",
+                    stored.summary()
+                ))?;
+                settings.stored = Some(stored);
+            }
+            Err(e) if e.is::<Reconnected>() => return Err(e),
+            Err(e) => {
+                let msg = format!(
+                    "Couldn't store the alphabet on the device, sending every glyph in full: {}",
+                    e
+                );
+                println!("{}", msg);
+                log.log(&msg)?;
+            }
+        }
+    }
     Ok(())
 }
+
+/// Everything that comes before the first trial, whatever the experiment
+fn start_session(
+    args: &RunArgs,
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    profile: &Profile,
+    log: SessionLog,
+) -> anyhow::Result<(Output, Settings)> {
+    let mut settings = Settings {
+        recalibrate: args.recalibrate,
+        intensity: args.intensity,
+        pulse_ms: args.pulse_ms,
+        stored: None,
+    };
+    let out = Output {
+        csv: Arc::new(Mutex::new(
+            csv::WriterBuilder::new().from_path(&args.out_path)?,
+        )),
+        log,
+        trial: Arc::new(AtomicUsize::new(0)),
+    };
+    // Right away, so that Ctrl-C while the alphabet uploads is logged as an abort too
+    handle_ctrl_c(dev.stop_handle(), out.clone());
+    // Before negotiating, so that the alphabet stored on the device is in the capture too and a
+    // replay on a fresh device has it
+    if let Some(path) = &args.capture {
+        dev.set_capture(Capture::create(path)?);
+    }
+    if let Err(e) = negotiate(dev, a_bet, profile, &mut settings, &out.log) {
+        out.log.log(&e.to_string()).unwrap_or(());
+        return Err(e);
+    }
+    Ok((out, settings))
+}
+
+async fn run_on(
+    args: &RunArgs,
+    dev: &mut dyn TactileDevice,
+    a_bet: &Alphabet,
+    profile: &Profile,
+    log: SessionLog,
+) -> anyhow::Result<()> {
+    let (out, settings) = start_session(args, dev, a_bet, profile, log)?;
+    run_exp(args.exp, &out, dev, a_bet, profile, &settings).await
+}
+
+fn create_session_log(args: &RunArgs, profile: &Profile) -> anyhow::Result<SessionLog> {
+    let log = SessionLog::create(&SessionLog::path_for(&args.out_path))?;
+    let devices = match &args.left {
+        Some(left) => format!(
+            "{} (left palm) and {} (right palm)",
+            left.display(),
+            args.tty_path.display()
+        ),
+        None => args.tty_path.display().to_string(),
+    };
+    log.log(&format!(
+        "{} experiment on {} ({} board)",
+        args.exp.to_possible_value().unwrap().get_name(),
+        devices,
+        profile.name
+    ))?;
+    Ok(log)
+}
+
+/// Opens one of the devices of a two-handed session
+fn open_hand(
+    tty_path: &Path,
+    profile: &Profile,
+    log: &SessionLog,
+) -> anyhow::Result<Box<dyn TactileDevice>> {
+    if tty_path == Path::new("sim") {
+        return Ok(Box::new(SimDevice::new(profile)));
+    }
+    let serial = block_in_place(|| open_serial(tty_path, profile))?;
+    let mut dev = ReconnectingDevice::new(serial, tty_path, profile.serial.baud_rate);
+    dev.set_log(log.clone());
+    Ok(Box::new(dev))
+}
+
+async fn run(args: RunArgs, profile: &Profile) -> anyhow::Result<()> {
+    if Path::exists(&args.out_path) && args.out_path != PathBuf::from("/dev/null") {
+        return Err(anyhow!("OUTPUT_FILE path already exists"));
+    }
+    let log_path = SessionLog::path_for(&args.out_path);
+    if Path::exists(&log_path) {
+        return Err(anyhow!("Session log {} already exists", log_path.display()));
+    }
+    if args.exp == Exp::CrossHand && args.left.is_none() {
+        return Err(anyhow!(NEEDS_TWO_HANDS));
+    }
+
+    let alphabet = args.alphabet.as_deref().unwrap_or(exp_alphabet(args.exp));
+    let a_bet = &Alphabet::find(alphabet, profile)?;
+    if let Some(name) = exp_glyph_names(args.exp, profile.motor_count())
+        .into_iter()
+        .find(|name| !a_bet.has_other_glyph(name))
+    {
+        return Err(anyhow!(
+            "The {} alphabet has no glyph called {}, the experiment needs it",
+            a_bet.name,
+            name
+        ));
+    }
+    a_bet.check(profile).map_err(|(glyph, e)| {
+        anyhow!(
+            "The {} board can't play glyph {} of the {} alphabet: {}",
+            profile.name,
+            glyph,
+            a_bet.name,
+            e
+        )
+    })?;
+
+    if let Some(left) = &args.left {
+        let log = create_session_log(&args, profile)?;
+        let right = open_hand(&args.tty_path, profile, &log)?;
+        let mut pair = DevicePair::new(open_hand(left, profile, &log)?, right);
+        if args.exp == Exp::CrossHand {
+            let (out, settings) = start_session(&args, &mut pair, a_bet, profile, log)?;
+            cross_hand_exp(&out, &mut pair, a_bet, &settings).await?;
+        } else {
+            run_on(&args, &mut pair, a_bet, profile, log).await?;
+        }
+    } else if args.tty_path == Path::new("sim") {
+        let mut dev = SimDevice::new(profile);
+        let log = create_session_log(&args, profile)?;
+        run_on(&args, &mut dev, a_bet, profile, log).await?;
+        print_sim_summary(&dev);
+    } else {
+        let serial = block_in_place(|| open_serial(&args.tty_path, profile))?;
+        let mut dev = ReconnectingDevice::new(serial, &args.tty_path, profile.serial.baud_rate);
+        let log = create_session_log(&args, profile)?;
+        dev.set_log(log.clone());
+        run_on(&args, &mut dev, a_bet, profile, log).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let profile = match &cli.profile {
+        Some(path) => Profile::load(path)?,
+        None => Profile::prototype(),
+    };
+
+    match (cli.command, cli.run) {
+        (Some(Command::Inspect { path }), _) => inspect(&path, &profile),
+        (Some(Command::Replay { path, tty_path }), _) => {
+            if tty_path == Path::new("sim") {
+                let mut dev = SimDevice::new(&profile);
+                replay(&path, &mut dev).await?;
+                print_sim_summary(&dev);
+                Ok(())
+            } else {
+                let mut dev = block_in_place(|| open_serial(&tty_path, &profile))?;
+                replay(&path, &mut dev).await
+            }
+        }
+        (Some(Command::Devices), _) => block_in_place(|| list_devices(&profile)),
+        (
+            Some(Command::BenchLink {
+                tty_path,
+                frames,
+                step_ms,
+            }),
+            _,
+        ) => block_in_place(|| bench_link(&tty_path, frames, step_ms, &profile)),
+        (
+            Some(Command::Stream {
+                tty_path,
+                text,
+                alphabet,
+                gap_ms,
+            }),
+            _,
+        ) => {
+            let a_bet = Alphabet::find(&alphabet, &profile)?;
+            let mut timeline = Timeline::default();
+            for c in text.chars() {
+                timeline.push_glyph(a_bet.get_glyph(c));
+                timeline.push_gap(gap_ms);
+            }
+            if tty_path == Path::new("sim") {
+                let mut dev = SimDevice::new(&profile);
+                block_in_place(|| stream_text(&mut dev, &timeline))?;
+                print_sim_summary(&dev);
+                Ok(())
+            } else {
+                block_in_place(|| stream_text(&mut open_serial(&tty_path, &profile)?, &timeline))
+            }
+        }
+        (
+            Some(Command::Play {
+                tty_path,
+                glyph,
+                alphabet,
+            }),
+            _,
+        ) => {
+            let a_bet = Alphabet::find(&alphabet, &profile)?;
+            let glyph = a_bet.parse_glyph(&glyph, profile.motor_count())?;
+            println_glyph(&profile, &glyph);
+            if tty_path == Path::new("sim") {
+                let mut dev = SimDevice::new(&profile);
+                play_glyph(&mut dev, &glyph).await?;
+                print_sim_summary(&dev);
+                Ok(())
+            } else {
+                let mut dev = block_in_place(|| open_serial(&tty_path, &profile))?;
+                play_glyph(&mut dev, &glyph).await
+            }
+        }
+        (Some(Command::ExportAlphabet { alphabet, out_path }), _) => {
+            export_alphabet(&alphabet, &out_path, &profile)
+        }
+        (None, Some(args)) => run(args, &profile).await,
+        (None, None) => unreachable!("clap requires arguments or a subcommand"),
+    }
+}
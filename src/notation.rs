@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::{
+    event::{Ev, EvType, EV_CHORD},
+    glyphs::{check_fits, equal_spaced_chords, equal_spaced_evs, stitch_evs},
+};
+
+/*
+Glyphs can be written in a short notation, for alphabet files, the command line and pilot sessions:
+  8-4-0 @30                motors pulsed one after another, 30 ms apart
+  [0 1 2 3]-[4 5 6 7] @30  groups of motors pulsed together
+  col0_up + col1_down      named glyphs played back to back
+  col0_up + 3-7-11 @50     both
+A glyph ends one spacing after its last pulse, the same as in alphabet files.
+*/
+
+/// Parses a glyph written in the notation above. `named` are the glyphs it can be stitched from,
+/// motors have to be below `motor_count`.
+pub fn parse_glyph(
+    text: &str,
+    motor_count: u8,
+    named: &HashMap<String, Vec<Ev>>,
+) -> anyhow::Result<Vec<Ev>> {
+    let parts = text
+        .split('+')
+        .map(|part| parse_part(part.trim(), motor_count, named))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    match parts.as_slice() {
+        [glyph] => Ok(glyph.clone()),
+        _ => {
+            let parts: Vec<&[Ev]> = parts.iter().map(Vec::as_slice).collect();
+            stitch_evs(&parts).ok_or_else(|| anyhow!("the glyphs together last too long"))
+        }
+    }
+}
+
+/// A glyph in the notation, None if it can't be written in it. Only glyphs of full strength,
+/// default length pulses evenly spaced can, see `parse_glyph`.
+pub fn format_glyph(glyph: &[Ev]) -> Option<String> {
+    let (end, events) = glyph.split_last()?;
+    let steps = events
+        .iter()
+        .map(|ev| match ev.ev_type {
+            EvType::Motor(motor) => Some(motor.to_string()),
+            EvType::Chord(motors) => {
+                let motors: Vec<String> = motors.iter().map(|m| m.to_string()).collect();
+                Some(format!("[{}]", motors.join(" ")))
+            }
+            EvType::EndGlyph => None,
+        })
+        .collect::<Option<Vec<String>>>()?;
+    if steps.is_empty() {
+        return None;
+    }
+    let text = format!("{} @{}", steps.join("-"), end.ms_time / steps.len() as u16);
+    // Whatever the notation can't say shows up as a difference once it is read back
+    let parsed = parse_glyph(&text, EV_CHORD, &HashMap::new()).ok()?;
+    (parsed == glyph).then_some(text)
+}
+
+/// Names of the glyphs a glyph in the notation is stitched from
+pub fn glyph_names(text: &str) -> Vec<&str> {
+    text.split('+')
+        .map(str::trim)
+        .filter(|part| !part.contains('@'))
+        .collect()
+}
+
+fn parse_part(
+    part: &str,
+    motor_count: u8,
+    named: &HashMap<String, Vec<Ev>>,
+) -> anyhow::Result<Vec<Ev>> {
+    if part.is_empty() {
+        return Err(anyhow!("there is an empty glyph between the +"));
+    }
+    let Some((steps, space)) = part.split_once('@') else {
+        return named.get(part).cloned().ok_or_else(|| {
+            if part.starts_with(|c: char| c.is_ascii_digit() || c == '[') {
+                anyhow!("{} needs a spacing, e.g. {} @30", part, part)
+            } else {
+                anyhow!("there is no glyph called {}", part)
+            }
+        });
+    };
+    let space = space.trim();
+    let space_ms: u16 = space
+        .strip_suffix("ms")
+        .unwrap_or(space)
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("{} after @ isn't a spacing in milliseconds", space))?;
+    let steps = steps
+        .split('-')
+        .map(|step| parse_step(step.trim(), motor_count))
+        .collect::<anyhow::Result<Vec<Step>>>()?;
+    check_fits(steps.len(), space_ms)?;
+    if steps.iter().all(|step| matches!(step, Step::Motor(_))) {
+        let motors: Vec<u8> = steps.iter().flat_map(Step::motors).collect();
+        Ok(equal_spaced_evs(&motors, space_ms))
+    } else {
+        let chords: Vec<Vec<u8>> = steps.iter().map(|step| step.motors().collect()).collect();
+        let chords: Vec<&[u8]> = chords.iter().map(Vec::as_slice).collect();
+        Ok(equal_spaced_chords(&chords, space_ms))
+    }
+}
+
+enum Step {
+    Motor(u8),
+    Chord(Vec<u8>),
+}
+
+impl Step {
+    fn motors(&self) -> impl Iterator<Item = u8> + '_ {
+        match self {
+            Step::Motor(motor) => std::slice::from_ref(motor).iter().copied(),
+            Step::Chord(motors) => motors.iter().copied(),
+        }
+    }
+}
+
+fn parse_step(step: &str, motor_count: u8) -> anyhow::Result<Step> {
+    if step.is_empty() {
+        return Err(anyhow!("there is a step without a motor between the -"));
+    }
+    let Some(chord) = step.strip_prefix('[') else {
+        return Ok(Step::Motor(parse_motor(step, motor_count)?));
+    };
+    let chord = chord
+        .strip_suffix(']')
+        .ok_or_else(|| anyhow!("{} is missing its closing ]", step))?;
+    let motors = chord
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|motor| !motor.is_empty())
+        .map(|motor| parse_motor(motor, motor_count))
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+    if motors.is_empty() {
+        return Err(anyhow!("{} plays no motors", step));
+    }
+    Ok(Step::Chord(motors))
+}
+
+fn parse_motor(motor: &str, motor_count: u8) -> anyhow::Result<u8> {
+    let index: u8 = motor
+        .parse()
+        .map_err(|_| anyhow!("{} isn't a motor number", motor))?;
+    if index >= motor_count {
+        return Err(anyhow!(
+            "there is no motor {}, the motors go from 0 to {}",
+            index,
+            motor_count.saturating_sub(1)
+        ));
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glyphs::set_intensity;
+
+    fn parse(text: &str) -> anyhow::Result<Vec<Ev>> {
+        let named = HashMap::from([("up".to_owned(), equal_spaced_evs(&[8, 4, 0], 30))]);
+        parse_glyph(text, 12, &named)
+    }
+
+    #[test]
+    fn motors_chords_and_named_glyphs() {
+        assert_eq!(
+            parse("8-4-0 @30").unwrap(),
+            equal_spaced_evs(&[8, 4, 0], 30)
+        );
+        assert_eq!(
+            parse(" 8 - 4 - 0 @ 30ms ").unwrap(),
+            equal_spaced_evs(&[8, 4, 0], 30)
+        );
+        assert_eq!(
+            parse("[0 1, 2]-5 @40").unwrap(),
+            equal_spaced_chords(&[&[0, 1, 2], &[5]], 40)
+        );
+        assert_eq!(parse("up").unwrap(), equal_spaced_evs(&[8, 4, 0], 30));
+        let up = equal_spaced_evs(&[8, 4, 0], 30);
+        let across = equal_spaced_evs(&[0, 1], 50);
+        assert_eq!(
+            parse("up + 0-1 @50").unwrap(),
+            stitch_evs(&[&up, &across]).unwrap()
+        );
+        assert_eq!(glyph_names("up + 0-1 @50 + down"), ["up", "down"]);
+    }
+
+    #[test]
+    fn errors_say_what_is_wrong() {
+        let cases = [
+            (
+                "3-12 @30",
+                "there is no motor 12, the motors go from 0 to 11",
+            ),
+            ("3-x @30", "x isn't a motor number"),
+            ("8-4-0", "8-4-0 needs a spacing, e.g. 8-4-0 @30"),
+            ("down", "there is no glyph called down"),
+            ("up + ", "there is an empty glyph between the +"),
+            ("3--4 @30", "there is a step without a motor between the -"),
+            ("[3 4-5 @30", "[3 4 is missing its closing ]"),
+            ("[]-5 @30", "[] plays no motors"),
+            ("3-4 @fast", "fast after @ isn't a spacing in milliseconds"),
+        ];
+        for (text, message) in cases {
+            assert_eq!(parse(text).unwrap_err().to_string(), message, "{}", text);
+        }
+        let too_long = parse("3-4 @40000").unwrap_err().to_string();
+        assert!(too_long.starts_with("it would last longer than"));
+    }
+
+    #[test]
+    fn formatted_glyphs_parse_back_the_same() {
+        let glyphs = [
+            equal_spaced_evs(&[8, 4, 0], 30),
+            equal_spaced_evs(&[11], 200),
+            equal_spaced_chords(&[&[0, 1, 2, 3], &[7], &[8, 11]], 45),
+        ];
+        for glyph in glyphs {
+            let text = format_glyph(&glyph).unwrap();
+            assert_eq!(parse(&text).unwrap(), glyph, "{}", text);
+        }
+        assert_eq!(
+            format_glyph(&equal_spaced_evs(&[8, 4, 0], 30)).unwrap(),
+            "8-4-0 @30"
+        );
+    }
+
+    #[test]
+    fn only_evenly_spaced_full_strength_glyphs_format() {
+        let uneven = [Ev::motor(0, 1), Ev::motor(10, 2), Ev::end(60)];
+        assert_eq!(format_glyph(&uneven), None);
+        let quiet = set_intensity(&equal_spaced_evs(&[1, 2], 30), 100);
+        assert_eq!(format_glyph(&quiet), None);
+        assert_eq!(format_glyph(&[Ev::end(30)]), None);
+        assert_eq!(format_glyph(&[]), None);
+    }
+}
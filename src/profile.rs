@@ -0,0 +1,176 @@
+use std::{fs, path::Path};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use crate::event::{check_glyph, Ev, GlyphError, EV_CHORD};
+
+/// The board the experiments were designed on, used when no profile is given
+const PROTOTYPE: &str = include_str!("../profiles/prototype.toml");
+
+/// Where a motor sits on the palm, see profiles/prototype.toml for the axes
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Motor {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+}
+
+/// Everything the host assumes about a board, loaded from a TOML file
+#[derive(Clone, Debug, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub timing_resolution_ms: u16, // Event times are rounded down to a multiple of this
+    pub motors: Vec<Motor>,        // EvType::Motor(i) plays `motors[i]`
+    pub serial: SerialSettings,
+}
+
+impl Profile {
+    pub fn prototype() -> Self {
+        Self::parse(PROTOTYPE).expect("the built in prototype profile is valid")
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Can't read device profile {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| anyhow!("Bad device profile {}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let profile: Self = toml::from_str(text)?;
+        // Every event type byte below EV_CHORD is free for motors
+        if profile.motors.is_empty() || profile.motors.len() > EV_CHORD as usize {
+            return Err(anyhow!(
+                "a board has between 1 and {} motors, not {}",
+                EV_CHORD as usize,
+                profile.motors.len()
+            ));
+        }
+        for (i, motor) in profile.motors.iter().enumerate() {
+            if !motor.x.is_finite() || !motor.y.is_finite() {
+                return Err(anyhow!(
+                    "motor {} is at ({}, {}), its coordinates have to be numbers",
+                    i,
+                    motor.x,
+                    motor.y
+                ));
+            }
+            let earlier = profile.motors[..i]
+                .iter()
+                .position(|m| m.x == motor.x && m.y == motor.y);
+            if let Some(j) = earlier {
+                return Err(anyhow!(
+                    "motors {} and {} are both at ({}, {})",
+                    j,
+                    i,
+                    motor.x,
+                    motor.y
+                ));
+            }
+        }
+        if profile.timing_resolution_ms == 0 {
+            return Err(anyhow!("timing_resolution_ms can't be 0"));
+        }
+        Ok(profile)
+    }
+
+    pub fn motor_count(&self) -> u8 {
+        self.motors.len() as u8
+    }
+
+    /// Whether the board can play a glyph, same check the firmware makes
+    pub fn check_glyph(&self, glyph: &[Ev]) -> Result<(), GlyphError> {
+        check_glyph(glyph, self.motor_count())
+    }
+
+    /// Rounds a time down to when the firmware would actually play it
+    pub fn quantize_ms(&self, ms: u16) -> u16 {
+        ms - ms % self.timing_resolution_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A profile file with the given motors, e.g. "{ x = 0, y = 0 }, { x = 1, y = 0 }"
+    fn profile_text(motors: &str) -> String {
+        format!(
+            "name = \"test\"\ntiming_resolution_ms = 5\nmotors = [{}]\n\
+             [serial]\nbaud_rate = 115200\n",
+            motors
+        )
+    }
+
+    #[test]
+    fn valid_profiles_parse() {
+        let prototype = Profile::prototype();
+        assert_eq!(prototype.name, "prototype");
+        assert_eq!(prototype.motor_count(), 12);
+        let profile =
+            Profile::parse(&profile_text("{ x = 0, y = 0 }, { x = 1.5, y = -2 }")).unwrap();
+        assert_eq!(profile.motor_count(), 2);
+        assert_eq!((profile.motors[1].x, profile.motors[1].y), (1.5, -2.0));
+        assert_eq!(profile.quantize_ms(123), 120);
+        let most: Vec<String> = (0..EV_CHORD)
+            .map(|x| format!("{{ x = {}, y = 0 }}", x))
+            .collect();
+        assert!(Profile::parse(&profile_text(&most.join(", "))).is_ok());
+    }
+
+    #[test]
+    fn boards_the_program_cant_drive_are_errors() {
+        let text = profile_text("{ x = 0, y = 0 }");
+        let too_many: Vec<String> = (0..=EV_CHORD)
+            .map(|x| format!("{{ x = {}, y = 0 }}", x))
+            .collect();
+        let cases = [
+            (
+                profile_text(""),
+                format!("a board has between 1 and {} motors, not 0", EV_CHORD),
+            ),
+            (
+                profile_text(&too_many.join(", ")),
+                format!(
+                    "a board has between 1 and {} motors, not {}",
+                    EV_CHORD,
+                    EV_CHORD as usize + 1
+                ),
+            ),
+            (
+                text.replace("timing_resolution_ms = 5", "timing_resolution_ms = 0"),
+                "timing_resolution_ms can't be 0".to_owned(),
+            ),
+        ];
+        for (text, message) in cases {
+            assert_eq!(Profile::parse(&text).unwrap_err().to_string(), message);
+        }
+        assert!(Profile::parse(&text.replace("[serial]", "")).is_err());
+    }
+
+    #[test]
+    fn motors_need_a_place_of_their_own() {
+        let cases = [
+            (
+                "{ x = 0, y = 0 }, { x = nan, y = 1 }",
+                "motor 1 is at (NaN, 1), its coordinates have to be numbers",
+            ),
+            (
+                "{ x = 0, y = -inf }",
+                "motor 0 is at (0, -inf), its coordinates have to be numbers",
+            ),
+            (
+                "{ x = 0, y = 0 }, { x = 1, y = 0 }, { x = 1, y = 0.0 }",
+                "motors 1 and 2 are both at (1, 0)",
+            ),
+        ];
+        for (motors, message) in cases {
+            let error = Profile::parse(&profile_text(motors)).unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+}
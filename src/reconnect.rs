@@ -0,0 +1,168 @@
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    capture::Capture,
+    device::{DeviceStatus, Disconnected, Playback, SerialDevice, StopHandle, TactileDevice},
+    discover::find_device,
+    event::{Capabilities, Ev, StoreSummary, TimeScale},
+    session::SessionLog,
+};
+
+/// How often to look for the device while it is gone
+const RECONNECT_POLL: Duration = Duration::from_millis(500);
+
+/// A device call failed because the connection dropped, it has since been reopened but whatever
+/// the call was doing has to be started over
+#[derive(Debug)]
+pub struct Reconnected;
+
+impl std::fmt::Display for Reconnected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The device was disconnected and has been reconnected")
+    }
+}
+
+impl std::error::Error for Reconnected {}
+
+/// A serial device that waits for the port to come back when it is unplugged and reopens it
+pub struct ReconnectingDevice {
+    dev: SerialDevice,
+    tty_path: PathBuf, // "auto" to search for the device again
+    baud_rate: u32,
+    log: Option<SessionLog>,
+}
+
+impl ReconnectingDevice {
+    pub fn new(dev: SerialDevice, tty_path: &Path, baud_rate: u32) -> Self {
+        Self {
+            dev,
+            tty_path: tty_path.to_owned(),
+            baud_rate,
+            log: None,
+        }
+    }
+
+    /// Note every reconnect in the session log
+    pub fn set_log(&mut self, log: SessionLog) {
+        self.log = Some(log);
+    }
+
+    fn note(&self, msg: &str) {
+        eprintln!("{}", msg);
+        if let Some(log) = self.log.as_ref() {
+            // Not being able to write the log is no reason to give up on the device
+            log.log(msg).unwrap_or(());
+        }
+    }
+
+    fn reopen(&self) -> anyhow::Result<SerialDevice> {
+        if self.tty_path == Path::new("auto") {
+            Ok(find_device(self.baud_rate)?.1)
+        } else {
+            SerialDevice::open(&self.tty_path, self.baud_rate)
+        }
+    }
+
+    /// Blocks until the device can be opened again, however long that takes
+    fn reconnect(&mut self) {
+        self.note(&format!(
+            "Lost connection to {}, waiting for the device to come back",
+            self.tty_path.display()
+        ));
+        let lost = Instant::now();
+        let mut dev = loop {
+            thread::sleep(RECONNECT_POLL);
+            if let Ok(dev) = self.reopen() {
+                break dev;
+            }
+        };
+        dev.carry_over(&mut self.dev);
+        self.dev = dev;
+        self.note(&format!(
+            "Reconnected to {} after {:.1} s",
+            self.tty_path.display(),
+            lost.elapsed().as_secs_f32()
+        ));
+    }
+
+    fn call<T>(
+        &mut self,
+        f: impl FnOnce(&mut SerialDevice) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        match f(&mut self.dev) {
+            Err(e) if e.is::<Disconnected>() => {
+                self.reconnect();
+                Err(Reconnected.into())
+            }
+            result => result,
+        }
+    }
+}
+
+impl TactileDevice for ReconnectingDevice {
+    fn send_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.call(|dev| dev.send_glyph(glyph))
+    }
+
+    fn queue_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.call(|dev| dev.queue_glyph(glyph))
+    }
+
+    fn wait_onset(&mut self, timeout: Duration) -> anyhow::Result<Option<u64>> {
+        self.call(|dev| dev.wait_onset(timeout))
+    }
+
+    fn store_glyph(&mut self, id: u16, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.call(|dev| dev.store_glyph(id, glyph))
+    }
+
+    fn play_stored(&mut self, id: u16, scale: TimeScale) -> anyhow::Result<()> {
+        self.call(|dev| dev.play_stored(id, scale))
+    }
+
+    fn stored_glyphs(&mut self) -> anyhow::Result<StoreSummary> {
+        self.call(|dev| dev.stored_glyphs())
+    }
+
+    fn clear_stored(&mut self) -> anyhow::Result<()> {
+        self.call(|dev| dev.clear_stored())
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.call(|dev| dev.send_raw(bytes))
+    }
+
+    fn set_capture(&mut self, capture: Capture) {
+        self.dev.set_capture(capture);
+    }
+
+    fn wait_playback(&mut self, timeout: Duration) -> anyhow::Result<Option<Playback>> {
+        self.call(|dev| dev.wait_playback(timeout))
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.call(|dev| dev.stop())
+    }
+
+    fn status(&mut self) -> anyhow::Result<DeviceStatus> {
+        self.call(|dev| dev.status())
+    }
+
+    fn capabilities(&mut self) -> anyhow::Result<Capabilities> {
+        self.call(|dev| dev.capabilities())
+    }
+
+    /// Carried over to the device a reconnect opens
+    fn set_clock_origin(&mut self, origin: Instant) {
+        self.dev.set_clock_origin(origin);
+    }
+
+    /// Keeps working across reconnects, the new connection takes over the old one's link
+    fn stop_handle(&self) -> Option<StopHandle> {
+        self.dev.stop_handle()
+    }
+}
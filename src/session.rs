@@ -0,0 +1,53 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+
+/// Notes about a session that don't belong in the CSV rows, such as the device dropping out.
+/// Clones share the same file, so the device layer and the experiment can both write to it.
+#[derive(Clone)]
+pub struct SessionLog {
+    out: Arc<Mutex<BufWriter<File>>>,
+    start: Instant,
+}
+
+impl SessionLog {
+    /// The log goes next to the experiment's output file, with ".log" appended to its name
+    pub fn path_for(out_path: &Path) -> PathBuf {
+        if out_path == Path::new("/dev/null") {
+            return out_path.to_owned();
+        }
+        let mut path = out_path.as_os_str().to_owned();
+        path.push(".log");
+        PathBuf::from(path)
+    }
+
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let unix_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        writeln!(out, "# tactom session log")?;
+        writeln!(out, "# started at unix time {}", unix_time)?;
+        out.flush()?;
+        Ok(Self {
+            out: Arc::new(Mutex::new(out)),
+            start: Instant::now(),
+        })
+    }
+
+    /// Appends a line stamped with the seconds since the session started
+    pub fn log(&self, msg: &str) -> anyhow::Result<()> {
+        let secs = self.start.elapsed().as_secs_f32();
+        let mut out = self
+            .out
+            .lock()
+            .map_err(|_| anyhow!("Session log poisoned"))?;
+        writeln!(out, "[{:>9.3} s] {}", secs, msg)?;
+        out.flush()?;
+        Ok(())
+    }
+}
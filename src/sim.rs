@@ -0,0 +1,701 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    capture::Capture,
+    device::{DeviceStatus, Playback, StopHandle, TactileDevice},
+    event::{
+        decode_glyph_payload, decode_play_stored_payload, describe_device_error, encode_glyph,
+        encode_packet, encode_play_stored, encode_queued_glyph, encode_store_glyph,
+        split_store_payload, store_checksum, Capabilities, DeviceMsg, DeviceMsgDecoder, Ev,
+        EvType::EndGlyph, PacketDecoder, PacketError, StoreSummary, TimeScale, ERR_BAD_CRC,
+        ERR_BAD_VERSION, ERR_GLYPH_TOO_LONG, ERR_MALFORMED_FRAME, ERR_NO_STORED_GLYPH,
+        ERR_STORE_FULL, ERR_UNKNOWN_COMMAND, FEATURE_ACK, FEATURE_CHORDS, FEATURE_ECHO,
+        FEATURE_GLYPH_STORE, FEATURE_INTENSITY, FEATURE_PULSE_MS, FEATURE_QUEUE, PKT_CLEAR_STORE,
+        PKT_ECHO, PKT_GLYPH, PKT_IDENTIFY, PKT_PLAY_STORED, PKT_QUERY_CAPS, PKT_QUERY_STORE,
+        PKT_QUEUE_GLYPH, PKT_STOP, PKT_STORE_GLYPH, PROTOCOL_VERSION,
+    },
+    glyphs::println_glyph,
+    profile::Profile,
+};
+
+const SIM_IDENTITY: &str = "tactom simulator";
+/// Size of the simulated firmware's receive buffer
+const SIM_MAX_GLYPH_LEN: u16 = 1024;
+/// Payload bytes the simulated firmware can keep with PKT_STORE_GLYPH, all glyphs together
+const SIM_STORE_LEN: usize = 16 * 1024;
+
+/// A single motor activation, timed on the clock passed to `Firmware::feed`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Firing {
+    pub at_ms: u64,
+    pub motor: u8,
+    pub intensity: u8,
+    pub pulse_ms: u16,
+    pub glyph: usize, // index of the frame this firing came from
+}
+
+/// Software model of the tactom firmware.
+///
+/// Bytes are fed in as they would arrive on the serial line. Once a full packet has arrived its
+/// events are scheduled relative to the arrival time, cancelling whatever was still queued from
+/// the previous glyph, just like the firmware does. A PKT_QUEUE_GLYPH is scheduled from the end of
+/// the playing glyph instead.
+pub struct Firmware {
+    profile: Profile,
+    packets: PacketDecoder,
+    timeline: Vec<Firing>,
+    glyph_count: usize,
+    playing_since_ms: u64,
+    playing_until_ms: u64,
+    finish_pending: bool, // PKT_FINISHED still has to be sent for the playing glyph
+    queued: Option<(usize, Vec<Ev>)>, // Glyph waiting for the playing one to end, and its index
+    store: BTreeMap<u16, Vec<Ev>>, // Glyphs kept with PKT_STORE_GLYPH, by id
+    dropped_frames: usize,
+    stop_count: usize,
+    output: Vec<u8>, // Bytes waiting to be sent back to the host
+}
+
+impl Firmware {
+    /// Firmware running on the board `profile` describes
+    pub fn new(profile: &Profile) -> Self {
+        Self {
+            profile: profile.clone(),
+            packets: PacketDecoder::default(),
+            timeline: vec![],
+            glyph_count: 0,
+            playing_since_ms: 0,
+            playing_until_ms: 0,
+            finish_pending: false,
+            queued: None,
+            store: BTreeMap::new(),
+            dropped_frames: 0,
+            stop_count: 0,
+            output: vec![],
+        }
+    }
+
+    /// Consume bytes that arrived at `now_ms`, returns the glyphs decoded from any completed frames
+    pub fn feed(&mut self, now_ms: u64, bytes: &[u8]) -> Vec<Vec<Ev>> {
+        self.tick(now_ms);
+        let mut glyphs = vec![];
+        for &byte in bytes {
+            let packet = match self.packets.push(byte) {
+                None => continue,
+                Some(Ok(packet)) => packet,
+                Some(Err(e)) => {
+                    self.reject(match e {
+                        PacketError::BadCrc => ERR_BAD_CRC,
+                        PacketError::BadVersion(_) => ERR_BAD_VERSION,
+                        PacketError::TooShort | PacketError::BadEscape => ERR_MALFORMED_FRAME,
+                    });
+                    continue;
+                }
+            };
+            match packet.kind {
+                PKT_GLYPH | PKT_QUEUE_GLYPH => {
+                    let caps = self.capabilities();
+                    match decode_glyph_payload(&packet.payload, caps.motor_count)
+                        .and_then(|glyph| caps.check_glyph(&glyph).map(|()| glyph))
+                    {
+                        Ok(glyph) => {
+                            self.accept(now_ms, &glyph, packet.kind == PKT_QUEUE_GLYPH);
+                            glyphs.push(glyph);
+                        }
+                        Err(e) => self.reject(e.device_error_code()),
+                    }
+                }
+                PKT_STORE_GLYPH => match self.store(&packet.payload) {
+                    Ok(()) => self.send(DeviceMsg::Ack),
+                    Err(code) => self.reject(code),
+                },
+                PKT_PLAY_STORED => match self.stored_glyph(&packet.payload) {
+                    Ok(glyph) => {
+                        self.accept(now_ms, &glyph, false);
+                        glyphs.push(glyph);
+                    }
+                    Err(code) => self.reject(code),
+                },
+                PKT_QUERY_STORE => {
+                    let summary = self.store_summary();
+                    self.send(DeviceMsg::StoreSummary(summary));
+                }
+                PKT_CLEAR_STORE => {
+                    self.send(DeviceMsg::Ack);
+                    self.store.clear();
+                }
+                PKT_IDENTIFY => self.send(DeviceMsg::Identity(SIM_IDENTITY.to_owned())),
+                PKT_QUERY_CAPS => {
+                    let caps = self.capabilities();
+                    self.send(DeviceMsg::Capabilities(caps));
+                }
+                PKT_ECHO => self.send(DeviceMsg::Echo(packet.payload)),
+                PKT_STOP => {
+                    self.send(DeviceMsg::Ack);
+                    self.cancel(now_ms);
+                    self.stop_count += 1;
+                }
+                _ => self.reject(ERR_UNKNOWN_COMMAND),
+            }
+        }
+        glyphs
+    }
+
+    /// What the simulated firmware reports in PKT_CAPABILITIES, it supports every feature
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            protocol_version: PROTOCOL_VERSION,
+            motor_count: self.profile.motor_count(),
+            max_glyph_len: SIM_MAX_GLYPH_LEN,
+            features: FEATURE_CHORDS
+                | FEATURE_INTENSITY
+                | FEATURE_PULSE_MS
+                | FEATURE_ECHO
+                | FEATURE_QUEUE
+                | FEATURE_GLYPH_STORE
+                | FEATURE_ACK,
+        }
+    }
+
+    /// What a PKT_STORE_SUMMARY reports
+    pub fn store_summary(&self) -> StoreSummary {
+        StoreSummary {
+            count: self.store.len() as u16,
+            checksum: store_checksum(&self.store),
+        }
+    }
+
+    /// Keeps the glyph in a PKT_STORE_GLYPH payload, or the code to reject it with
+    fn store(&mut self, payload: &[u8]) -> Result<(), u8> {
+        let (id, payload) = split_store_payload(payload).ok_or(ERR_MALFORMED_FRAME)?;
+        let caps = self.capabilities();
+        let glyph = decode_glyph_payload(payload, caps.motor_count)
+            .and_then(|glyph| caps.check_glyph(&glyph).map(|()| glyph))
+            .map_err(|e| e.device_error_code())?;
+        let others: usize = self
+            .store
+            .iter()
+            .filter(|&(&other, _)| other != id)
+            .map(|(_, glyph)| glyph.iter().map(Ev::payload_len).sum::<usize>())
+            .sum();
+        if others + payload.len() > SIM_STORE_LEN {
+            return Err(ERR_STORE_FULL);
+        }
+        self.store.insert(id, glyph);
+        Ok(())
+    }
+
+    /// The stored glyph a PKT_PLAY_STORED payload asks for, scaled, or the code to reject it with
+    fn stored_glyph(&self, payload: &[u8]) -> Result<Vec<Ev>, u8> {
+        let (id, scale) = decode_play_stored_payload(payload).ok_or(ERR_MALFORMED_FRAME)?;
+        let glyph = self.store.get(&id).ok_or(ERR_NO_STORED_GLYPH)?;
+        scale.scale_glyph(glyph).ok_or(ERR_GLYPH_TOO_LONG)
+    }
+
+    /// Advance the clock without receiving anything, sending PKT_FINISHED if a glyph ended and
+    /// starting the queued one if there is one
+    pub fn tick(&mut self, now_ms: u64) {
+        // The queued glyph may have ended by now too
+        while self.finish_pending && now_ms >= self.playing_until_ms {
+            self.finish_pending = false;
+            self.send(DeviceMsg::Finished);
+            if let Some((glyph_idx, glyph)) = self.queued.take() {
+                // The firmware starts it right away, not when it next hears from the host
+                self.play(self.playing_until_ms, glyph_idx, &glyph);
+            }
+        }
+    }
+
+    fn reject(&mut self, code: u8) {
+        self.send(DeviceMsg::Error(code));
+        self.dropped_frames += 1;
+    }
+
+    /// Acknowledges a glyph and plays it, or queues it if asked to and one is playing
+    fn accept(&mut self, now_ms: u64, glyph: &[Ev], queue: bool) {
+        self.send(DeviceMsg::Ack);
+        let glyph_idx = self.glyph_count;
+        self.glyph_count += 1;
+        if queue && self.is_playing(now_ms) {
+            self.queued = Some((glyph_idx, glyph.to_vec()));
+        } else {
+            self.play(now_ms, glyph_idx, glyph);
+        }
+    }
+
+    fn send(&mut self, msg: DeviceMsg) {
+        self.output.extend(msg.encode());
+    }
+
+    fn play(&mut self, now_ms: u64, glyph_idx: usize, glyph: &[Ev]) {
+        self.cancel(now_ms);
+        self.playing_since_ms = now_ms;
+        self.playing_until_ms = now_ms;
+        self.finish_pending = true;
+        self.send(DeviceMsg::Started);
+        for ev in glyph {
+            let at_ms = now_ms + self.profile.quantize_ms(ev.ms_time) as u64;
+            if ev.ev_type == EndGlyph {
+                self.playing_until_ms = at_ms;
+                break;
+            }
+            for motor in ev.motors().iter() {
+                self.timeline.push(Firing {
+                    at_ms,
+                    motor,
+                    intensity: ev.intensity,
+                    pulse_ms: ev.pulse_ms,
+                    glyph: glyph_idx,
+                });
+            }
+        }
+    }
+
+    /// Bytes the firmware has sent back since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Drop every firing that has not happened yet and the queued glyph, a glyph cut short never
+    /// reports PKT_FINISHED
+    pub fn cancel(&mut self, now_ms: u64) {
+        self.tick(now_ms);
+        self.timeline.retain(|f| f.at_ms <= now_ms);
+        self.playing_until_ms = self.playing_until_ms.min(now_ms);
+        self.finish_pending = false;
+        self.queued = None;
+    }
+
+    pub fn is_playing(&self, now_ms: u64) -> bool {
+        now_ms < self.playing_until_ms
+    }
+
+    /// Whether a glyph is waiting for the playing one to end
+    pub fn has_queued(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// Start and end time of the glyph received last
+    pub fn last_playback(&self) -> (u64, u64) {
+        (self.playing_since_ms, self.playing_until_ms)
+    }
+
+    /// Every firing scheduled so far, including ones that are still in the future
+    pub fn timeline(&self) -> &[Firing] {
+        &self.timeline
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped_frames
+    }
+
+    /// How many PKT_STOPs were received
+    pub fn stop_count(&self) -> usize {
+        self.stop_count
+    }
+}
+
+/// Runs a fresh firmware over a byte stream until the other end closes it, printing every glyph as
+/// it arrives. The stream must time out reads after a few milliseconds, so that PKT_FINISHED goes
+/// out close to when the glyph actually ends.
+pub fn serve(profile: &Profile, link: &mut (impl Read + Write)) -> io::Result<()> {
+    let start = Instant::now();
+    let mut firmware = Firmware::new(profile);
+    let mut dropped = 0;
+    let mut stops = 0;
+    let mut buf = [0; 256];
+    loop {
+        let n = match link.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                0
+            }
+            Err(e) => return Err(e),
+        };
+        let now_ms = Instant::now().duration_since(start).as_millis() as u64;
+        let glyphs = firmware.feed(now_ms, &buf[..n]);
+        let first_idx = firmware.glyph_count() - glyphs.len();
+        for (i, glyph) in glyphs.into_iter().enumerate() {
+            println!("[{:>8} ms] glyph {}:", now_ms, first_idx + i);
+            for ev in glyph.iter() {
+                println!("  {:>5} ms -> {}", ev.ms_time, ev);
+            }
+            if !glyph.is_empty() {
+                println_glyph(profile, &glyph);
+            }
+        }
+        link.write_all(&firmware.take_output())?;
+        if firmware.dropped_frames() != dropped {
+            dropped = firmware.dropped_frames();
+            println!(
+                "[{:>8} ms] dropped malformed frame ({} so far)",
+                now_ms, dropped
+            );
+        }
+        if firmware.stop_count() != stops {
+            stops = firmware.stop_count();
+            println!("[{:>8} ms] stopped", now_ms);
+        }
+    }
+}
+
+/// In-process device backed by the firmware simulator, for running experiments without hardware
+pub struct SimDevice {
+    start: Instant,
+    firmware: Firmware,
+    capture: Option<Capture>,
+}
+
+impl SimDevice {
+    pub fn new(profile: &Profile) -> Self {
+        Self {
+            start: Instant::now(),
+            firmware: Firmware::new(profile),
+            capture: None,
+        }
+    }
+
+    fn now_ms(&self) -> u64 {
+        Instant::now().duration_since(self.start).as_millis() as u64
+    }
+
+    pub fn firmware(&self) -> &Firmware {
+        &self.firmware
+    }
+
+    /// Feeds the firmware a packet it acknowledges, turning a PKT_ERROR into an error
+    fn send_command(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        self.firmware.take_output();
+        self.send_raw(packet)?;
+        let mut decoder = DeviceMsgDecoder::default();
+        for byte in self.firmware.take_output() {
+            if let Some(DeviceMsg::Error(code)) = decoder.push(byte) {
+                return Err(anyhow!(
+                    "Device rejected the command: {}",
+                    describe_device_error(code)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TactileDevice for SimDevice {
+    fn send_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.firmware
+            .capabilities()
+            .check_glyph(glyph)
+            .map_err(|e| anyhow!("Invalid glyph, {}", e))?;
+        self.send_raw(&encode_glyph(glyph))
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.record(bytes)?;
+        }
+        let now_ms = self.now_ms();
+        self.firmware.feed(now_ms, bytes);
+        Ok(())
+    }
+
+    fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    fn queue_glyph(&mut self, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.firmware
+            .capabilities()
+            .check_glyph(glyph)
+            .map_err(|e| anyhow!("Invalid glyph, {}", e))?;
+        self.send_raw(&encode_queued_glyph(glyph))
+    }
+
+    fn store_glyph(&mut self, id: u16, glyph: &[Ev]) -> anyhow::Result<()> {
+        self.send_command(&encode_store_glyph(id, glyph))
+    }
+
+    fn play_stored(&mut self, id: u16, scale: TimeScale) -> anyhow::Result<()> {
+        self.send_command(&encode_play_stored(id, scale))
+    }
+
+    fn stored_glyphs(&mut self) -> anyhow::Result<StoreSummary> {
+        Ok(self.firmware.store_summary())
+    }
+
+    fn clear_stored(&mut self) -> anyhow::Result<()> {
+        self.send_command(&encode_packet(PKT_CLEAR_STORE, &[]))
+    }
+
+    fn wait_onset(&mut self, timeout: Duration) -> anyhow::Result<Option<u64>> {
+        if self.firmware.has_queued() {
+            let (_, until_ms) = self.firmware.last_playback();
+            let remaining = Duration::from_millis(until_ms.saturating_sub(self.now_ms()));
+            if remaining > timeout {
+                thread::sleep(timeout);
+                return Err(anyhow!("Device did not report the start of playback"));
+            }
+            thread::sleep(remaining);
+            let now_ms = self.now_ms();
+            self.firmware.tick(now_ms);
+        }
+        Ok(Some(self.firmware.last_playback().0))
+    }
+
+    fn wait_playback(&mut self, timeout: Duration) -> anyhow::Result<Option<Playback>> {
+        let deadline = Instant::now() + timeout;
+        self.wait_onset(timeout)?;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let (onset_ms, offset_ms) = self.firmware.last_playback();
+        let remaining = Duration::from_millis(offset_ms.saturating_sub(self.now_ms()));
+        if remaining > timeout {
+            thread::sleep(timeout);
+            return Err(anyhow!("Device did not report the end of playback"));
+        }
+        thread::sleep(remaining);
+        Ok(Some(Playback {
+            onset_ms,
+            offset_ms,
+        }))
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.send_raw(&encode_packet(PKT_STOP, &[]))
+    }
+
+    fn status(&mut self) -> anyhow::Result<DeviceStatus> {
+        let now_ms = self.now_ms();
+        self.firmware.tick(now_ms);
+        if self.firmware.is_playing(now_ms) {
+            Ok(DeviceStatus::Playing)
+        } else {
+            Ok(DeviceStatus::Idle)
+        }
+    }
+
+    fn capabilities(&mut self) -> anyhow::Result<Capabilities> {
+        Ok(self.firmware.capabilities())
+    }
+
+    fn set_clock_origin(&mut self, origin: Instant) {
+        self.start = origin;
+    }
+
+    /// The simulator lives in this process, it stops playing when the program exits
+    fn stop_handle(&self) -> Option<StopHandle> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{
+        encode_packet, DeviceMsgDecoder, ERR_BAD_EVENT_TYPE, ERR_GLYPH_TOO_LONG, PKT_STOP,
+    };
+
+    fn replies(firmware: &mut Firmware) -> Vec<DeviceMsg> {
+        let mut decoder = DeviceMsgDecoder::default();
+        let output = firmware.take_output();
+        output
+            .iter()
+            .filter_map(|&byte| decoder.push(byte))
+            .collect()
+    }
+
+    /// Motors pulsed 20 ms apart
+    fn glyph(motors: &[u8]) -> Vec<Ev> {
+        let events = motors.iter().enumerate();
+        let mut glyph: Vec<Ev> = events.map(|(i, &m)| Ev::motor(i as u16 * 20, m)).collect();
+        glyph.push(Ev::end(motors.len() as u16 * 20));
+        glyph
+    }
+
+    fn firings(firmware: &Firmware) -> Vec<(u64, u8)> {
+        firmware
+            .timeline()
+            .iter()
+            .map(|f| (f.at_ms, f.motor))
+            .collect()
+    }
+
+    #[test]
+    fn glyph_is_acked_started_then_finished() {
+        let mut firmware = Firmware::new(&Profile::prototype());
+        let played = firmware.feed(100, &encode_glyph(&glyph(&[8, 4, 0])));
+        assert_eq!(played, vec![glyph(&[8, 4, 0])]);
+        assert_eq!(replies(&mut firmware), [DeviceMsg::Ack, DeviceMsg::Started]);
+        assert_eq!(firings(&firmware), [(100, 8), (120, 4), (140, 0)]);
+
+        firmware.tick(159);
+        assert_eq!(replies(&mut firmware), []);
+        firmware.tick(160);
+        assert_eq!(replies(&mut firmware), [DeviceMsg::Finished]);
+        assert_eq!(firmware.last_playback(), (100, 160));
+    }
+
+    #[test]
+    fn new_glyph_cuts_the_last_one_short() {
+        let mut firmware = Firmware::new(&Profile::prototype());
+        firmware.feed(0, &encode_glyph(&glyph(&[0, 1, 2])));
+        firmware.feed(30, &encode_glyph(&glyph(&[5])));
+        firmware.tick(100);
+        assert_eq!(firings(&firmware), [(0, 0), (20, 1), (30, 5)]);
+        assert_eq!(firmware.glyph_count(), 2);
+        // Only the glyph that played to its end reports finishing
+        assert_eq!(
+            replies(&mut firmware),
+            [
+                DeviceMsg::Ack,
+                DeviceMsg::Started,
+                DeviceMsg::Ack,
+                DeviceMsg::Started,
+                DeviceMsg::Finished
+            ]
+        );
+    }
+
+    #[test]
+    fn queued_glyph_starts_when_the_playing_one_ends() {
+        let mut firmware = Firmware::new(&Profile::prototype());
+        firmware.feed(0, &encode_glyph(&glyph(&[0, 1])));
+        firmware.feed(10, &encode_queued_glyph(&glyph(&[2, 3])));
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Ack, DeviceMsg::Started, DeviceMsg::Ack]
+        );
+        assert!(firmware.has_queued());
+
+        firmware.tick(40);
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Finished, DeviceMsg::Started]
+        );
+        firmware.tick(80);
+        assert_eq!(replies(&mut firmware), [DeviceMsg::Finished]);
+        assert_eq!(firings(&firmware), [(0, 0), (20, 1), (40, 2), (60, 3)]);
+    }
+
+    #[test]
+    fn stop_cuts_the_glyph_short_without_finishing_it() {
+        let mut firmware = Firmware::new(&Profile::prototype());
+        firmware.feed(0, &encode_glyph(&glyph(&[0, 1, 2])));
+        firmware.feed(10, &encode_packet(PKT_STOP, &[]));
+        firmware.tick(100);
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Ack, DeviceMsg::Started, DeviceMsg::Ack]
+        );
+        assert_eq!(firings(&firmware), [(0, 0)]);
+        assert_eq!(firmware.stop_count(), 1);
+    }
+
+    #[test]
+    fn bad_packets_are_rejected_with_their_error_code() {
+        let mut firmware = Firmware::new(&Profile::prototype());
+
+        let mut bad_crc = encode_glyph(&glyph(&[0]));
+        let crc_lo = bad_crc.len() - 2;
+        bad_crc[crc_lo] ^= 0x01;
+        firmware.feed(0, &bad_crc);
+        assert_eq!(replies(&mut firmware), [DeviceMsg::Error(ERR_BAD_CRC)]);
+
+        firmware.feed(0, &encode_glyph(&glyph(&[13])));
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Error(ERR_BAD_EVENT_TYPE)]
+        );
+
+        // A payload cut off part way through an event
+        firmware.feed(0, &encode_packet(PKT_GLYPH, &[0, 0]));
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Error(ERR_MALFORMED_FRAME)]
+        );
+
+        let too_long = glyph(&[0; 200]);
+        firmware.feed(0, &encode_glyph(&too_long));
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Error(ERR_GLYPH_TOO_LONG)]
+        );
+
+        firmware.feed(0, &encode_play_stored(3, TimeScale { num: 1, den: 1 }));
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Error(ERR_NO_STORED_GLYPH)]
+        );
+
+        firmware.feed(0, &encode_packet(0x7F, &[]));
+        assert_eq!(
+            replies(&mut firmware),
+            [DeviceMsg::Error(ERR_UNKNOWN_COMMAND)]
+        );
+
+        assert_eq!(firmware.dropped_frames(), 6);
+        assert_eq!(firmware.glyph_count(), 0);
+    }
+
+    #[test]
+    fn store_fills_up_but_replacing_a_glyph_still_fits() {
+        let mut firmware = Firmware::new(&Profile::prototype());
+        // 100 events of 6 bytes each, 27 of them fit in 16 KiB
+        let big = glyph(&[5; 99]);
+        for id in 0..27 {
+            firmware.feed(0, &encode_store_glyph(id, &big));
+            assert_eq!(replies(&mut firmware), [DeviceMsg::Ack], "glyph {}", id);
+        }
+        firmware.feed(0, &encode_store_glyph(27, &big));
+        assert_eq!(replies(&mut firmware), [DeviceMsg::Error(ERR_STORE_FULL)]);
+        firmware.feed(0, &encode_store_glyph(26, &big));
+        assert_eq!(replies(&mut firmware), [DeviceMsg::Ack]);
+        assert_eq!(firmware.store_summary().count, 27);
+    }
+
+    #[test]
+    fn stored_glyphs_play_scaled_and_are_summed_up() {
+        let mut firmware = Firmware::new(&Profile::prototype());
+        let (a, b) = (glyph(&[0, 1, 2]), glyph(&[11]));
+        firmware.feed(0, &encode_store_glyph(1, &a));
+        firmware.feed(0, &encode_store_glyph(2, &b));
+        firmware.feed(0, &encode_packet(PKT_QUERY_STORE, &[]));
+        let expected = StoreSummary {
+            count: 2,
+            checksum: store_checksum(&BTreeMap::from([(1, a.clone()), (2, b.clone())])),
+        };
+        assert_eq!(
+            replies(&mut firmware),
+            [
+                DeviceMsg::Ack,
+                DeviceMsg::Ack,
+                DeviceMsg::StoreSummary(expected)
+            ]
+        );
+        // The checksum covers what is stored, not just how many
+        let other = store_checksum(&BTreeMap::from([(1, b.clone()), (2, a.clone())]));
+        assert_ne!(expected.checksum, other);
+
+        let played = firmware.feed(100, &encode_play_stored(1, TimeScale { num: 3, den: 2 }));
+        assert_eq!(played.len(), 1);
+        assert_eq!(firings(&firmware), [(100, 0), (130, 1), (160, 2)]);
+        assert_eq!(firmware.last_playback(), (100, 190));
+
+        firmware.feed(200, &encode_packet(PKT_CLEAR_STORE, &[]));
+        assert_eq!(firmware.store_summary().count, 0);
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    device::TactileDevice,
+    event::{Capabilities, Ev, EvType::EndGlyph, EVENT_LEN, FEATURE_QUEUE},
+    glyphs::glyph_duration,
+};
+
+/// Longest a glyph can last, its event times are u16 milliseconds
+const MAX_GLYPH_MS: u64 = u16::MAX as u64;
+/// How late a chunk may start before the stream is given up on
+const ONSET_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A sequence of events timed in milliseconds from its start, with no limit on how long it lasts
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    events: Vec<(u64, Ev)>, // Never EndGlyph, times never go back
+    end_ms: u64,
+}
+
+impl Timeline {
+    /// Adds a glyph to play once everything before it has ended
+    pub fn push_glyph(&mut self, glyph: &[Ev]) {
+        let start_ms = self.end_ms;
+        for ev in glyph {
+            let at_ms = start_ms + ev.ms_time as u64;
+            if ev.ev_type == EndGlyph {
+                self.end_ms = at_ms;
+                break;
+            }
+            self.events.push((at_ms, *ev));
+            self.end_ms = self.end_ms.max(at_ms);
+        }
+    }
+
+    /// Adds a pause before whatever comes next
+    pub fn push_gap(&mut self, ms: u64) {
+        self.end_ms += ms;
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.end_ms
+    }
+
+    /// The whole timeline as one glyph, None if it lasts too long for one
+    pub fn to_glyph(&self) -> Option<Vec<Ev>> {
+        if self.end_ms > MAX_GLYPH_MS {
+            return None;
+        }
+        let events = self.events.iter().map(|&(at_ms, ev)| Ev {
+            ms_time: at_ms as u16,
+            ..ev
+        });
+        Some(events.chain([Ev::end(self.end_ms as u16)]).collect())
+    }
+
+    /// Splits the timeline into glyphs of at most `max_len` payload bytes that, played back to
+    /// back, make up the timeline. Each one ends exactly where the next one starts, so no drift
+    /// builds up from chunk to chunk. Pauses too long for a glyph become chunks that only wait.
+    pub fn chunks(&self, max_len: usize) -> Vec<Vec<Ev>> {
+        let mut chunks = vec![];
+        let mut start_ms = 0;
+        let mut chunk = vec![];
+        let mut len = EVENT_LEN; // The EndGlyph is always there
+        for &(at_ms, ev) in &self.events {
+            while at_ms - start_ms > MAX_GLYPH_MS {
+                chunks.push(end_chunk(&mut chunk, MAX_GLYPH_MS));
+                start_ms += MAX_GLYPH_MS;
+                len = EVENT_LEN;
+            }
+            if len + ev.payload_len() > max_len && !chunk.is_empty() {
+                chunks.push(end_chunk(&mut chunk, at_ms - start_ms));
+                start_ms = at_ms;
+                len = EVENT_LEN;
+            }
+            chunk.push(Ev {
+                ms_time: (at_ms - start_ms) as u16,
+                ..ev
+            });
+            len += ev.payload_len();
+        }
+        while self.end_ms - start_ms > MAX_GLYPH_MS {
+            chunks.push(end_chunk(&mut chunk, MAX_GLYPH_MS));
+            start_ms += MAX_GLYPH_MS;
+        }
+        chunks.push(end_chunk(&mut chunk, self.end_ms - start_ms));
+        chunks
+    }
+}
+
+fn end_chunk(chunk: &mut Vec<Ev>, end_ms: u64) -> Vec<Ev> {
+    chunk.push(Ev::end(end_ms as u16));
+    std::mem::take(chunk)
+}
+
+/// When a chunk of a stream was meant to start and when it did, in milliseconds. `planned_ms` is
+/// from the start of the stream, `onset_ms` is on the device's Playback clock and None if the device
+/// can't report playback.
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkPlayback {
+    pub planned_ms: u64,
+    pub onset_ms: Option<u64>,
+}
+
+/// Plays a timeline in chunks the firmware can buffer, returns when it has finished.
+///
+/// A firmware with FEATURE_QUEUE starts each chunk the moment the one before it ends, so there is
+/// neither a gap nor drift between them. Other firmware gets each chunk when the one before it
+/// should end going by the host's clock, its link latency then decides how clean the joins are.
+pub fn stream(
+    dev: &mut dyn TactileDevice,
+    timeline: &Timeline,
+    caps: &Capabilities,
+) -> anyhow::Result<Vec<ChunkPlayback>> {
+    let chunks = timeline.chunks(caps.max_glyph_len as usize);
+    let start = Instant::now();
+    let mut played = vec![];
+    let mut planned_ms = 0;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let timeout = if i > 0 && caps.has(FEATURE_QUEUE) {
+            dev.queue_glyph(chunk)?;
+            // It starts once the chunk before has played
+            let before = glyph_duration(&chunks[i - 1]);
+            Duration::from_millis(before as u64) + ONSET_TIMEOUT
+        } else {
+            let at = start + Duration::from_millis(planned_ms);
+            thread::sleep(at.saturating_duration_since(Instant::now()));
+            dev.send_glyph(chunk)?;
+            ONSET_TIMEOUT
+        };
+        // The next chunk can only be queued once this one is playing
+        let onset_ms = dev.wait_onset(timeout)?;
+        played.push(ChunkPlayback {
+            planned_ms,
+            onset_ms,
+        });
+        planned_ms += glyph_duration(chunk) as u64;
+    }
+    let last = chunks.last().map_or(0, |chunk| glyph_duration(chunk));
+    if dev
+        .wait_playback(Duration::from_millis(last as u64) + ONSET_TIMEOUT)?
+        .is_none()
+    {
+        let end = start + Duration::from_millis(planned_ms);
+        thread::sleep(end.saturating_duration_since(Instant::now()));
+    }
+    Ok(played)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{check_glyph, glyph_payload};
+
+    /// Chunk sizes to split into, the smallest only fits a chord and the EndGlyph
+    const MAX_LENS: [usize; 3] = [EVENT_LEN * 3, 64, 1024];
+
+    /// Glyphs of several sizes, with pauses longer than a glyph can last between and after them
+    fn long_timeline() -> Timeline {
+        let mut timeline = Timeline::default();
+        for i in 0..40u16 {
+            let mut glyph: Vec<Ev> = (0..i % 7)
+                .map(|j| Ev::motor(j * 25, (i + j) as u8 % 12))
+                .collect();
+            glyph.push(Ev::chord(i % 7 * 25, &[0, 5, 11]).with_intensity(100));
+            glyph.push(Ev::end(i % 7 * 25 + 30));
+            timeline.push_glyph(&glyph);
+            if i % 13 == 0 {
+                timeline.push_gap(70_000 + i as u64);
+            }
+        }
+        timeline.push_gap(140_000);
+        timeline
+    }
+
+    /// When each event plays, the times the events had in their glyphs don't count
+    fn played(timeline: &Timeline) -> Vec<(u64, Ev)> {
+        let events = timeline.events.iter();
+        events
+            .map(|&(at_ms, ev)| (at_ms, Ev { ms_time: 0, ..ev }))
+            .collect()
+    }
+
+    #[test]
+    fn chunks_play_back_as_the_timeline() {
+        let timeline = long_timeline();
+        assert!(timeline.duration_ms() > 3 * MAX_GLYPH_MS);
+        for max_len in MAX_LENS {
+            let chunks = timeline.chunks(max_len);
+            let mut rebuilt = Timeline::default();
+            for chunk in &chunks {
+                rebuilt.push_glyph(chunk);
+            }
+            assert_eq!(played(&rebuilt), played(&timeline), "max_len {}", max_len);
+            assert_eq!(rebuilt.end_ms, timeline.end_ms, "max_len {}", max_len);
+        }
+    }
+
+    #[test]
+    fn every_chunk_is_a_glyph_the_firmware_takes() {
+        let timeline = long_timeline();
+        for max_len in MAX_LENS {
+            for (i, chunk) in timeline.chunks(max_len).iter().enumerate() {
+                assert_eq!(check_glyph(chunk, 12), Ok(()), "chunk {}", i);
+                assert!(glyph_payload(chunk).len() <= max_len, "chunk {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn short_timeline_is_one_chunk() {
+        let glyph = [Ev::motor(0, 3), Ev::motor(40, 7), Ev::end(80)];
+        let mut timeline = Timeline::default();
+        timeline.push_glyph(&glyph);
+        assert_eq!(timeline.chunks(1024), vec![glyph.to_vec()]);
+        assert_eq!(timeline.to_glyph(), Some(glyph.to_vec()));
+    }
+}